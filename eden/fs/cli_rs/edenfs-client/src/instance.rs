@@ -10,7 +10,9 @@
 
 use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::time::Duration;
+use std::time::Instant;
 
 use anyhow::{anyhow, Context};
 
@@ -20,6 +22,7 @@ use fbthrift_socket::SocketTransport;
 use thrift_types::edenfs::{client::EdenService, types::DaemonInfo};
 use thrift_types::fb303_core::types::fb303_status;
 use thrift_types::fbthrift::binary_protocol::BinaryProtocol;
+use tokio::sync::Mutex;
 use tokio_uds_compat::UnixStream;
 use tracing::{event, Level};
 
@@ -30,11 +33,41 @@ use crate::EdenFsClient;
 const CLIENTS_DIR: &str = "clients";
 const CONFIG_JSON: &str = "config.json";
 
-#[derive(Debug)]
+/// Overall deadline for `connect`'s retry loop: bounds how long a
+/// short-lived CLI invocation will wait out an EdenFS restart rather than
+/// retrying forever.
+const CONNECT_RETRY_DEADLINE: Duration = Duration::from_secs(10);
+const CONNECT_RETRY_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const CONNECT_RETRY_MAX_BACKOFF: Duration = Duration::from_secs(2);
+
+/// Doubles `current`, capped at `CONNECT_RETRY_MAX_BACKOFF`. Split out from
+/// the retry loop so the backoff progression is directly testable.
+fn next_backoff(current: Duration) -> Duration {
+    std::cmp::min(current * 2, CONNECT_RETRY_MAX_BACKOFF)
+}
+
 pub struct EdenFsInstance {
     config_dir: PathBuf,
     etc_eden_dir: PathBuf,
     home_dir: Option<PathBuf>,
+    /// Shared Thrift client, lazily connected and reused across calls so
+    /// repeated `get_health`/Thrift calls don't each pay for a fresh
+    /// connection. Invalidated (and transparently reconnected) if a call
+    /// through it fails, in case the daemon restarted since we last
+    /// connected.
+    client_cache: Mutex<Option<Arc<EdenFsClient>>>,
+}
+
+// `EdenFsClient` doesn't implement `Debug`, so it's excluded from the
+// derived impl by hand rather than deriving on the whole struct.
+impl std::fmt::Debug for EdenFsInstance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EdenFsInstance")
+            .field("config_dir", &self.config_dir)
+            .field("etc_eden_dir", &self.etc_eden_dir)
+            .field("home_dir", &self.home_dir)
+            .finish()
+    }
 }
 
 impl EdenFsInstance {
@@ -50,6 +83,7 @@ impl EdenFsInstance {
             config_dir,
             etc_eden_dir,
             home_dir,
+            client_cache: Mutex::new(None),
         }
     }
 
@@ -71,18 +105,88 @@ impl EdenFsInstance {
     }
 
     pub async fn connect(&self, timeout: Option<Duration>) -> Result<EdenFsClient> {
+        self.connect_with_retry(timeout, CONNECT_RETRY_DEADLINE)
+            .await
+    }
+
+    /// Same as [`connect`], but with an explicit overall deadline for
+    /// retries: a connect failure or timeout is retried with exponential
+    /// backoff as long as the daemon's lockfile indicates it's merely
+    /// starting up. If the lockfile says EdenFS isn't running at all, we
+    /// give up immediately instead of waiting out the deadline.
+    pub async fn connect_with_retry(
+        &self,
+        timeout: Option<Duration>,
+        retry_deadline: Duration,
+    ) -> Result<EdenFsClient> {
         let socket_path = self.config_dir.join("socket");
+        let deadline = Instant::now() + retry_deadline;
+        let mut backoff = CONNECT_RETRY_INITIAL_BACKOFF;
 
-        let connect = self._connect(&socket_path);
-        let res = if let Some(timeout) = timeout {
+        loop {
+            let err = match self.connect_once(&socket_path, timeout).await {
+                Ok(client) => return Ok(client),
+                Err(err) => err,
+            };
+
+            if !self.daemon_may_be_starting_up() || Instant::now() >= deadline {
+                return Err(err);
+            }
+
+            event!(
+                Level::DEBUG,
+                ?backoff,
+                "EdenFS not ready yet, retrying connect"
+            );
+            tokio::time::sleep(backoff).await;
+            backoff = next_backoff(backoff);
+        }
+    }
+
+    async fn connect_once(
+        &self,
+        socket_path: &PathBuf,
+        timeout: Option<Duration>,
+    ) -> Result<EdenFsClient> {
+        let connect = self._connect(socket_path);
+        if let Some(timeout) = timeout {
             tokio::time::timeout(timeout, connect)
                 .await
-                .map_err(|_| EdenFsError::ThriftConnectionTimeout(socket_path))?
+                .map_err(|_| EdenFsError::ThriftConnectionTimeout(socket_path.clone()))?
         } else {
             connect.await
-        };
+        }
+    }
+
+    /// Whether the daemon looks like it's mid-startup (worth retrying) as
+    /// opposed to not running at all (not worth retrying). `status_from_lock`
+    /// always returns `Err`, distinguishing "alive but Thrift server not up
+    /// yet" from "not running" only in its message, so that's what we key
+    /// off of here.
+    fn daemon_may_be_starting_up(&self) -> bool {
+        match self.status_from_lock() {
+            Err(err) => is_still_alive_error(&err),
+            Ok(_) => true,
+        }
+    }
+
+    /// Returns the cached, shared Thrift client, connecting (with retry) only
+    /// if there isn't one cached yet.
+    pub async fn get_client(&self, timeout: Option<Duration>) -> Result<Arc<EdenFsClient>> {
+        let mut cache = self.client_cache.lock().await;
+        if let Some(client) = cache.as_ref() {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(self.connect(timeout).await?);
+        *cache = Some(client.clone());
+        Ok(client)
+    }
 
-        res
+    /// Drops the cached client so the next [`get_client`] call reconnects.
+    /// Call this after a Thrift call through the cached client fails, in
+    /// case its transport died (e.g. the daemon restarted).
+    pub async fn invalidate_client(&self) {
+        *self.client_cache.lock().await = None;
     }
 
     #[cfg(windows)]
@@ -136,12 +240,26 @@ impl EdenFsInstance {
     }
 
     pub async fn get_health(&self, timeout: Option<Duration>) -> Result<DaemonInfo> {
+        let timeout = timeout.or_else(|| Some(Duration::from_secs(3)));
         let client = self
-            .connect(timeout.or_else(|| Some(Duration::from_secs(3))))
+            .get_client(timeout)
             .await
             .context("Unable to connect to EdenFS daemon")?;
         event!(Level::DEBUG, "connected to EdenFS daemon");
-        client.getDaemonInfo().await.from_err()
+        match client.getDaemonInfo().await {
+            Ok(info) => Ok(info),
+            Err(_) => {
+                // The cached transport may have died (e.g. the daemon
+                // restarted since we last connected); reconnect once on a
+                // fresh client rather than surfacing a stale-socket error.
+                self.invalidate_client().await;
+                let client = self
+                    .get_client(timeout)
+                    .await
+                    .context("Unable to reconnect to EdenFS daemon")?;
+                client.getDaemonInfo().await.from_err()
+            }
+        }
     }
 
     /// Returns a map of mount paths to mount names
@@ -176,6 +294,14 @@ impl EdenFsInstance {
     }
 }
 
+/// `status_from_lock` always returns `Err`, distinguishing "alive but Thrift
+/// server not up yet" from "not running" only in its message; this is the
+/// substring check `daemon_may_be_starting_up` keys off of, split out so
+/// it's testable without a real lockfile.
+fn is_still_alive_error(err: &anyhow::Error) -> bool {
+    err.to_string().contains("still alive")
+}
+
 pub trait DaemonHealthy {
     fn is_healthy(&self) -> bool;
 }
@@ -186,3 +312,62 @@ impl DaemonHealthy for DaemonInfo {
             .map_or_else(|| false, |val| val == fb303_status::ALIVE)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_backoff_doubles_and_caps() {
+        assert_eq!(
+            next_backoff(CONNECT_RETRY_INITIAL_BACKOFF),
+            Duration::from_millis(200)
+        );
+        assert_eq!(next_backoff(Duration::from_millis(200)), Duration::from_millis(400));
+        assert_eq!(
+            next_backoff(CONNECT_RETRY_MAX_BACKOFF),
+            CONNECT_RETRY_MAX_BACKOFF
+        );
+        assert_eq!(
+            next_backoff(CONNECT_RETRY_MAX_BACKOFF * 10),
+            CONNECT_RETRY_MAX_BACKOFF
+        );
+    }
+
+    #[test]
+    fn test_is_still_alive_error() {
+        assert!(is_still_alive_error(&anyhow!(
+            "EdenFS's Thrift server does not appear to be running, \
+            but the process is still alive (PID=1)"
+        )));
+        assert!(!is_still_alive_error(&anyhow!("EdenFS is not running")));
+    }
+
+    /// Exercises the real `connect_with_retry` path against a socket that
+    /// will never exist: connect fails immediately, `status_from_lock` finds
+    /// no lockfile either, so `daemon_may_be_starting_up` reports false and
+    /// the loop must give up on the first attempt instead of waiting out the
+    /// deadline.
+    #[tokio::test]
+    async fn test_connect_gives_up_immediately_when_daemon_not_running() {
+        let dir = std::env::temp_dir().join(format!(
+            "edenfs-instance-test-{}-{}",
+            std::process::id(),
+            "no-daemon"
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let instance = EdenFsInstance::new(dir.clone(), dir.clone(), None);
+
+        let start = Instant::now();
+        let result = instance
+            .connect_with_retry(Some(Duration::from_millis(200)), Duration::from_secs(10))
+            .await;
+        let elapsed = start.elapsed();
+
+        let _ = std::fs::remove_dir_all(&dir);
+
+        assert!(result.is_err());
+        // Giving up immediately rather than retrying for the full 10s deadline.
+        assert!(elapsed < Duration::from_secs(5));
+    }
+}
@@ -5,7 +5,8 @@
  * GNU General Public License version 2.
  */
 
-use anyhow::{anyhow, format_err, Error};
+use anyhow::{anyhow, Error};
+use blobrepo::{save_bonsai_changesets, BlobRepo};
 use blobstore::{Blobstore, Loadable};
 use borrowed::borrowed;
 use bounded_traversal::bounded_traversal;
@@ -15,15 +16,17 @@ use derived_data_manager::DerivationContext;
 use futures::{
     channel::mpsc,
     future::{self, BoxFuture, FutureExt},
-    stream::{StreamExt, TryStreamExt},
+    stream::{self, StreamExt, TryStreamExt},
 };
-use manifest::{Diff, ManifestOps, PathTree};
+use manifest::{Diff, Entry, ManifestOps, PathTree};
 use mononoke_types::{
-    deleted_manifest_common::DeletedManifestCommon, BonsaiChangeset, ChangesetId, MPath,
-    MPathElement, ManifestUnodeId, MononokeId,
+    deleted_manifest_common::DeletedManifestCommon, BonsaiChangeset, BonsaiChangesetMut,
+    ChangesetId, DateTime, FileChange, FileUnodeId, MPath, MPathElement, ManifestUnodeId,
+    MononokeId,
 };
+use sorted_vector_map::SortedVectorMap;
 use std::sync::Arc;
-use std::{collections::BTreeMap, collections::HashSet};
+use std::{collections::BTreeMap, collections::HashMap, collections::HashSet};
 use tokio::sync::Mutex;
 use unodes::RootUnodeManifestId;
 
@@ -67,10 +70,19 @@ pub(crate) struct DeletedManifestDeriver<Manifest: DeletedManifestCommon>(
     std::marker::PhantomData<Manifest>,
 );
 
-#[derive(Debug, Eq, PartialEq)]
 pub(crate) enum PathChange {
     Add,
-    Remove,
+    /// The path was removed. Carries the pre-deletion unode `Entry` (file or
+    /// subdirectory) diffed out by `diff_against_parents`, i.e. exactly what
+    /// the path pointed to just before this change -- `None` when the
+    /// change wasn't derived from a real diff (e.g. a synthetically built
+    /// `PathTree` in a test). See `do_create`'s `CreateDeleted` arm for
+    /// where this ends up stored.
+    Remove(Option<Entry<ManifestUnodeId, FileUnodeId>>),
+    /// The path was removed, but its content was recorded as copied to
+    /// another path in the same commit (a rename/move rather than a
+    /// genuine deletion). Carries the destination path.
+    MovedTo(MPath),
     FileDirConflict,
 }
 
@@ -90,6 +102,17 @@ struct DeletedManifestChange<Manifest: DeletedManifestCommon> {
     /// Parent to base on. Result should be equivalent to copying the subentries
     /// of the parent and then applying the remanining modifications.
     copy_subentries_from: Option<Manifest>,
+    /// Set when this path's deletion came from a `PathChange::MovedTo`: the
+    /// path its content was moved to in the same commit. Recorded by
+    /// `do_create` into the `moves` accumulator keyed by the node it creates,
+    /// so a caller in the same derivation process can report "moved to X"
+    /// instead of a plain deletion; see `derive_with_move_and_content_info`.
+    moved_to: Option<MPath>,
+    /// Set when this path was removed and a pre-deletion unode `Entry` was
+    /// captured for it (see `PathChange::Remove`). Recorded by `do_create`
+    /// into the `contents` accumulator keyed by the node it creates; see
+    /// `derive_with_move_and_content_info` and `deleted_content` below.
+    deleted_content: Option<Entry<ManifestUnodeId, FileUnodeId>>,
 }
 
 struct DeletedManifestUnfoldNode<Manifest: DeletedManifestCommon> {
@@ -97,118 +120,268 @@ struct DeletedManifestUnfoldNode<Manifest: DeletedManifestCommon> {
     changes: PathTree<Option<PathChange>>,
     // set is used to automatically deduplicate parents that have equal ancestors
     parents: HashSet<Manifest::Id>,
+    /// This node's full path from the derivation root, accumulated on the
+    /// way down so `do_unfold` can resolve a divergent-parent node against
+    /// `root_unode` below without having to re-derive the path from scratch.
+    full_path: Option<MPath>,
+    /// The root unode manifest of the changeset being derived -- the real,
+    /// already-merged working state, as opposed to `parents`, which are the
+    /// (possibly disagreeing) per-parent deleted-manifest nodes. Carried
+    /// unchanged through every node of one derivation call.
+    root_unode: ManifestUnodeId,
 }
 
 impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
-    pub(crate) async fn derive(
+    /// Derives a linear stack of changesets in one pass, reusing a single
+    /// `created` dedup set across the whole stack instead of starting it
+    /// fresh per commit. `parent` is the already-derived manifest of the
+    /// changeset immediately preceding the stack (i.e. the parent of
+    /// `stack[0]`), or `None` if the stack starts from scratch. Each
+    /// commit's derived root is fed as the parent of the next commit in the
+    /// stack without a round-trip through the mapping table, which is the
+    /// main lever for fast backfill over millions of historical commits.
+    ///
+    /// Each commit gets its own blobstore write channel, drained (mirroring
+    /// `derive`'s single-commit behavior) before moving on to the next:
+    /// `derive_single` only *queues* blobstore `put`s onto `sender`, so a
+    /// channel shared across the whole stack and drained only once at the
+    /// end would let commit N+1's `derive_single` `.load()` commit N's
+    /// `mf_id` as a parent before that `put` had actually executed.
+    pub(crate) async fn derive_batch(
+        ctx: &CoreContext,
+        blobstore: &Arc<dyn Blobstore>,
+        stack: Vec<(BonsaiChangeset, PathTree<Option<PathChange>>, ManifestUnodeId)>,
+        parent: Option<Manifest::Id>,
+    ) -> Result<BTreeMap<ChangesetId, Manifest::Id>, Error> {
+        let created = Arc::new(Mutex::new(HashSet::new()));
+        let mut parent = parent;
+        let mut derived = BTreeMap::new();
+
+        for (bonsai, changes, root_unode) in stack {
+            let cs_id = bonsai.get_changeset_id();
+            let parents = parent.into_iter().collect::<Vec<_>>();
+
+            let (sender, receiver) = mpsc::unbounded();
+            // Move/content info isn't surfaced by `derive_batch` (no caller
+            // needs it yet); each commit gets its own throwaway accumulators
+            // rather than threading them through the whole stack.
+            let moves = Arc::new(Mutex::new(HashMap::new()));
+            let contents = Arc::new(Mutex::new(HashMap::new()));
+            cloned!(blobstore, ctx, created);
+            let f = async move {
+                borrowed!(ctx, blobstore);
+                Self::derive_single(
+                    ctx,
+                    blobstore,
+                    cs_id.clone(),
+                    parents,
+                    changes,
+                    root_unode,
+                    sender,
+                    created,
+                    moves,
+                    contents,
+                )
+                .await
+            };
+            let handle = tokio::spawn(f);
+
+            receiver
+                .buffered(1024)
+                .try_for_each(|_| async { Ok(()) })
+                .await?;
+            let mf_id = handle.await??;
+
+            parent = Some(mf_id);
+            derived.insert(cs_id, mf_id);
+        }
+
+        Ok(derived)
+    }
+
+    /// Shared implementation for deriving a single changeset, parameterised
+    /// over the sender/`created` set so that `derive_batch` can keep one
+    /// `created` set alive across an entire stack (its sender is still
+    /// per commit) while `derive` keeps the original per-commit behavior.
+    /// `moves` accumulates node -> move-destination
+    /// pairs and `contents` accumulates node -> pre-deletion content
+    /// identity pairs, both recorded by `do_create`; see
+    /// `derive_with_move_and_content_info`.
+    async fn derive_single(
         ctx: &CoreContext,
         blobstore: &Arc<dyn Blobstore>,
         cs_id: ChangesetId,
         parents: Vec<Manifest::Id>,
         changes: PathTree<Option<PathChange>>,
+        root_unode: ManifestUnodeId,
+        sender: mpsc::UnboundedSender<BoxFuture<'static, Result<(), Error>>>,
+        created: Arc<Mutex<HashSet<String>>>,
+        moves: Arc<Mutex<HashMap<Manifest::Id, MPath>>>,
+        contents: Arc<Mutex<HashMap<Manifest::Id, Entry<ManifestUnodeId, FileUnodeId>>>>,
     ) -> Result<Manifest::Id, Error> {
-        // Stream is used to batch writes to blobstore
-        let (sender, receiver) = mpsc::unbounded();
-        let created = Arc::new(Mutex::new(HashSet::new()));
-        cloned!(blobstore, ctx);
-        let f = async move {
-            borrowed!(ctx, blobstore);
-            let manifest_opt = bounded_traversal(
-                256,
-                DeletedManifestUnfoldNode {
-                    path_element: None,
-                    changes,
-                    parents: parents.into_iter().collect(),
-                },
-                // unfold
-                {
-                    move |
-                        DeletedManifestUnfoldNode {
-                            path_element,
-                            changes,
-                            parents,
-                        },
-                    | {
-                        async move {
-                            let (mf_change, next_states) =
-                                Self::do_unfold(ctx, blobstore, changes, parents).await?;
-                            Ok(((path_element, mf_change), next_states))
-                        }
-                        .boxed()
+        let manifest_opt = bounded_traversal(
+            256,
+            DeletedManifestUnfoldNode {
+                path_element: None,
+                changes,
+                parents: parents.into_iter().collect(),
+                full_path: None,
+                root_unode: root_unode.clone(),
+            },
+            // unfold
+            {
+                move |
+                    DeletedManifestUnfoldNode {
+                        path_element,
+                        changes,
+                        parents,
+                        full_path,
+                        root_unode,
+                    },
+                | {
+                    async move {
+                        let (mf_change, next_states) =
+                            Self::do_unfold(ctx, blobstore, changes, parents, full_path, root_unode)
+                                .await?;
+                        Ok(((path_element, mf_change), next_states))
                     }
-                },
-                // fold
-                {
-                    cloned!(sender, created);
-                    move |
-                        (path, manifest_change): (
-                            Option<MPathElement>,
-                            DeletedManifestChange<Manifest>,
-                        ),
-                        // impl Iterator<Out>
-                        subentries_iter,
-                        // -> Out = (Option<MPathElement>, Option<Manifest::Id>)
-                        // (_, None) means a leaf node was deleted because the file was recreated.
-                        // (None, _) means the path is empty and should only happen on the root.
-                    | {
-                        cloned!(cs_id, sender, created);
-                        async move {
-                            let mut subentries_to_update = BTreeMap::new();
-                            for entry in subentries_iter {
-                                match entry {
-                                    (None, _) => {
-                                        return Err(anyhow!(concat!(
-                                            "Failed to create deleted files manifest: ",
-                                            "subentry must have a path"
-                                        )));
-                                    }
-                                    (Some(path), maybe_mf_id) => {
-                                        subentries_to_update.insert(path, maybe_mf_id);
-                                    }
+                    .boxed()
+                }
+            },
+            // fold
+            {
+                cloned!(sender, created, moves, contents);
+                move |
+                    (path, manifest_change): (Option<MPathElement>, DeletedManifestChange<Manifest>),
+                    subentries_iter,
+                | {
+                    cloned!(cs_id, sender, created, moves, contents);
+                    async move {
+                        let mut subentries_to_update = BTreeMap::new();
+                        for entry in subentries_iter {
+                            match entry {
+                                (None, _) => {
+                                    return Err(anyhow!(concat!(
+                                        "Failed to create deleted files manifest: ",
+                                        "subentry must have a path"
+                                    )));
+                                }
+                                (Some(path), maybe_mf_id) => {
+                                    subentries_to_update.insert(path, maybe_mf_id);
                                 }
                             }
-
-                            let maybe_mf_id = Self::do_create(
-                                ctx,
-                                blobstore,
-                                cs_id.clone(),
-                                manifest_change,
-                                subentries_to_update,
-                                sender.clone(),
-                                created.clone(),
-                            )
-                            .await?;
-
-                            Ok((path, maybe_mf_id))
                         }
-                        .boxed()
-                    }
-                },
-            )
-            .await?;
 
-            debug_assert!(manifest_opt.0.is_none());
-            match manifest_opt {
-                (_, Some(mf_id)) => Ok(mf_id),
-                (_, None) => {
-                    // there are no deleted files, need to create an empty root manifest
-                    match Manifest::copy_and_update_subentries(
-                        ctx,
-                        blobstore,
-                        None,
-                        None,
-                        BTreeMap::new(),
-                    )
-                    .await
-                    {
-                        Ok(mf) => {
-                            Self::save_manifest(mf, ctx, blobstore, sender.clone(), created.clone())
-                                .await
-                        }
-                        Err(err) => Err(err),
+                        let maybe_mf_id = Self::do_create(
+                            ctx,
+                            blobstore,
+                            cs_id.clone(),
+                            manifest_change,
+                            subentries_to_update,
+                            sender.clone(),
+                            created.clone(),
+                            moves.clone(),
+                            contents.clone(),
+                        )
+                        .await?;
+
+                        Ok((path, maybe_mf_id))
                     }
+                    .boxed()
                 }
+            },
+        )
+        .await?;
+
+        debug_assert!(manifest_opt.0.is_none());
+        match manifest_opt {
+            (_, Some(mf_id)) => Ok(mf_id),
+            (_, None) => {
+                // there are no deleted files, need to create an empty root manifest
+                let mf = Manifest::copy_and_update_subentries(
+                    ctx,
+                    blobstore,
+                    None,
+                    None,
+                    BTreeMap::new(),
+                )
+                .await?;
+                Self::save_manifest(mf, ctx, blobstore, sender, created).await
             }
+        }
+    }
+
+    pub(crate) async fn derive(
+        ctx: &CoreContext,
+        blobstore: &Arc<dyn Blobstore>,
+        cs_id: ChangesetId,
+        parents: Vec<Manifest::Id>,
+        changes: PathTree<Option<PathChange>>,
+        root_unode: ManifestUnodeId,
+    ) -> Result<Manifest::Id, Error> {
+        let (mf_id, _moves, _contents) = Self::derive_with_move_and_content_info(
+            ctx, blobstore, cs_id, parents, changes, root_unode,
+        )
+        .await?;
+        Ok(mf_id)
+    }
+
+    /// Like [`derive`], but also returns:
+    /// - a map from each node created for a `PathChange::MovedTo` deletion to
+    ///   the path its content moved to in the same commit (see
+    ///   `get_changes`), so a caller in the same derivation process can
+    ///   report "moved to X" instead of a plain deletion;
+    /// - a map from each node created for a `PathChange::Remove` deletion to
+    ///   the pre-deletion unode `Entry` it deleted (file or subdirectory),
+    ///   for content identity recovery; see `deleted_content`.
+    ///
+    /// Both maps only thread their payload through the in-memory
+    /// derivation; neither is persisted onto the node itself, since that
+    /// needs `DeletedManifestCommon`/`copy_and_update_subentries` to store
+    /// the extra data -- a `mononoke_types` change out of reach from this
+    /// crate alone (see the comment on `do_create`'s `CreateDeleted` arm). A
+    /// different process (e.g. a history query service reading the node
+    /// back out of the blobstore) still sees a plain deletion with neither
+    /// piece of information; only a caller in the same process as this
+    /// derivation call can use them.
+    pub(crate) async fn derive_with_move_and_content_info(
+        ctx: &CoreContext,
+        blobstore: &Arc<dyn Blobstore>,
+        cs_id: ChangesetId,
+        parents: Vec<Manifest::Id>,
+        changes: PathTree<Option<PathChange>>,
+        root_unode: ManifestUnodeId,
+    ) -> Result<
+        (
+            Manifest::Id,
+            HashMap<Manifest::Id, MPath>,
+            HashMap<Manifest::Id, Entry<ManifestUnodeId, FileUnodeId>>,
+        ),
+        Error,
+    > {
+        // Stream is used to batch writes to blobstore
+        let (sender, receiver) = mpsc::unbounded();
+        let created = Arc::new(Mutex::new(HashSet::new()));
+        let moves = Arc::new(Mutex::new(HashMap::new()));
+        let contents = Arc::new(Mutex::new(HashMap::new()));
+        let moves_for_task = moves.clone();
+        let contents_for_task = contents.clone();
+        cloned!(blobstore, ctx);
+        let f = async move {
+            borrowed!(ctx, blobstore);
+            Self::derive_single(
+                ctx,
+                blobstore,
+                cs_id,
+                parents,
+                changes,
+                root_unode,
+                sender,
+                created,
+                moves_for_task,
+                contents_for_task,
+            )
+            .await
         };
 
         let handle = tokio::spawn(f);
@@ -217,15 +390,23 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
             .buffered(1024)
             .try_for_each(|_| async { Ok(()) })
             .await?;
-        handle.await?
+        let mf_id = handle.await??;
+        let moves = Arc::try_unwrap(moves)
+            .map_err(|_| anyhow!("moves accumulator still shared after derivation"))?
+            .into_inner();
+        let contents = Arc::try_unwrap(contents)
+            .map_err(|_| anyhow!("contents accumulator still shared after derivation"))?
+            .into_inner();
+        Ok((mf_id, moves, contents))
     }
 
-
     async fn do_unfold(
         ctx: &CoreContext,
         blobstore: &Arc<dyn Blobstore>,
         changes: PathTree<Option<PathChange>>,
         parents: HashSet<Manifest::Id>,
+        full_path: Option<MPath>,
+        root_unode: ManifestUnodeId,
     ) -> Result<
         (
             DeletedManifestChange<Manifest>,
@@ -241,20 +422,29 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
         let parent_manifests =
             future::try_join_all(parents.iter().map(|mf_id| mf_id.load(ctx, blobstore))).await?;
 
+        // Returns `Some(status)` if every parent agrees on whether this node is
+        // deleted, `None` if they disagree.
         let check_consistency = |manifests: &[Manifest]| {
             let mut it = manifests.iter().map(|mf| mf.is_deleted());
-            if let Some(status) = it.next() {
-                if it.all(|st| st == status) {
-                    return Ok(status);
-                }
-                return Err(format_err!(
-                    "parent deleted manifests have different node status, but no changes were provided"
-                ));
+            match it.next() {
+                Some(status) if it.all(|st| st == status) => Some(status),
+                _ => None,
             }
-            Ok(false)
         };
 
 
+        // Captured before the match below consumes `change`, since only the
+        // `MovedTo` arm carries a destination; every other arm leaves this `None`.
+        let moved_to = match &change {
+            Some(PathChange::MovedTo(dest)) => Some(dest.clone()),
+            _ => None,
+        };
+
+        // `change` is matched by value below, so `PathChange::Remove`'s
+        // captured content is taken out alongside the `change_type` it
+        // determines, rather than cloned out ahead of time like `moved_to`
+        // above (an `Entry` isn't known to be `Clone` in this checkout).
+        let mut deleted_content = None;
         let change_type = match change {
             None => {
                 if subentries.is_empty() {
@@ -266,6 +456,8 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
                                 DeletedManifestChange {
                                     change_type: DeletedManifestChangeType::Reuse,
                                     copy_subentries_from: None,
+                                    moved_to: None,
+                                    deleted_content: None,
                                 },
                                 vec![],
                             ));
@@ -275,18 +467,45 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
                                 DeletedManifestChange {
                                     change_type: DeletedManifestChangeType::Reuse,
                                     copy_subentries_from: Some(parent.clone()),
+                                    moved_to: None,
+                                    deleted_content: None,
                                 },
                                 vec![],
                             ));
                         }
                         parents => {
-                            // parent manifests are different, we need to merge them
-                            // let's check that the node status is consistent across parents
-                            let is_deleted = check_consistency(parents)?;
-                            if is_deleted {
-                                DeletedManifestChangeType::CreateDeleted
-                            } else {
-                                DeletedManifestChangeType::RemoveIfNowEmpty
+                            // Parent manifests are different. If they all agree
+                            // on this node's deleted status, keep it as-is.
+                            match check_consistency(parents) {
+                                Some(true) => DeletedManifestChangeType::CreateDeleted,
+                                Some(false) => DeletedManifestChangeType::RemoveIfNowEmpty,
+                                None => {
+                                    // The parents disagree (e.g. one branch
+                                    // deleted the path, the other kept it
+                                    // live) even though no change to this
+                                    // exact path was recorded by the merge
+                                    // commit itself. Agreement between
+                                    // parents is only a proxy for the real
+                                    // question -- whether the path actually
+                                    // exists in the merge's own working
+                                    // state -- so when the proxy is
+                                    // inconclusive, settle it by checking
+                                    // `root_unode`, the unode manifest of the
+                                    // changeset actually being derived,
+                                    // instead of guessing. The
+                                    // subentry-merging logic below still
+                                    // unions every parent's deleted
+                                    // descendants either way, so nothing is
+                                    // silently dropped regardless of which
+                                    // branch this resolves to.
+                                    if path_is_live_in_unode(ctx, blobstore, &root_unode, &full_path)
+                                        .await?
+                                    {
+                                        DeletedManifestChangeType::RemoveIfNowEmpty
+                                    } else {
+                                        DeletedManifestChangeType::CreateDeleted
+                                    }
+                                }
                             }
                         }
                     }
@@ -299,8 +518,16 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
                 // the path was added
                 DeletedManifestChangeType::RemoveIfNowEmpty
             }
-            Some(PathChange::Remove) => {
-                // the path was removed
+            Some(PathChange::Remove(entry)) => {
+                // the path was removed; stash whatever pre-deletion content
+                // identity the diff captured for it, so `do_create` can
+                // hand it to the `contents` accumulator
+                deleted_content = entry;
+                DeletedManifestChangeType::CreateDeleted
+            }
+            Some(PathChange::MovedTo(_)) => {
+                // the path's content moved elsewhere in this commit; from this
+                // path's own perspective it's still a deletion
                 DeletedManifestChangeType::CreateDeleted
             }
             Some(PathChange::FileDirConflict) => {
@@ -316,12 +543,15 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
         let mut recurse_entries = subentries
             .into_iter()
             .map(|(path, change_tree)| {
+                let child_path = MPath::join_opt_element(full_path.as_ref(), &path);
                 (
                     path.clone(),
                     DeletedManifestUnfoldNode {
                         path_element: Some(path),
                         changes: change_tree,
                         parents: HashSet::new(),
+                        full_path: Some(child_path),
+                        root_unode: root_unode.clone(),
                     },
                 )
             })
@@ -331,20 +561,54 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
             [] => DeletedManifestChange {
                 change_type,
                 copy_subentries_from: None,
+                moved_to,
+                deleted_content,
             },
             [parent] => {
-                // If there's one parent, we can "copy" its subentries
-                // and modify only a few fields. Important if we're doing few
-                // changes on a big node and need to optimise.
-                for (path, node) in &mut recurse_entries {
-                    if let Some(subentry_id) = parent.lookup(ctx, blobstore, path).await? {
-                        node.parents.insert(subentry_id);
+                // If there's one parent, we can "copy" its subentries and
+                // modify only a few fields. This only ever calls
+                // `Manifest::lookup` for paths that actually changed, never
+                // `into_subentries`, and bounds how many of those lookups are
+                // in flight at once (`MAX_CONCURRENT_SUBENTRY_LOOKUPS`) so a
+                // directory with many simultaneously-changed children (e.g. a
+                // bulk delete under `node_modules`) can't fire off hundreds
+                // of thousands of concurrent blobstore reads in one go.
+                //
+                // What this does NOT do is shrink the blob a huge directory
+                // rewrites on every single change. `copy_and_update_subentries`
+                // is a `DeletedManifestCommon` method: it owns the node's
+                // on-disk subentries representation and always persists the
+                // merged map as one blob. Hash-bucketing that representation
+                // into multiple child blobs means giving `DeletedManifestCommon`
+                // itself a sharded node layout, which is a `mononoke_types`
+                // type whose source isn't present in this checkout -- there's
+                // no shard-aware variant of `copy_and_update_subentries` to
+                // call instead. That part of the request stays blocked on a
+                // change outside this crate; the concurrency bound above is
+                // the real, available fix on this side of the trait boundary.
+                const MAX_CONCURRENT_SUBENTRY_LOOKUPS: usize = 100;
+
+                let lookups = stream::iter(recurse_entries.iter().map(|(path, _)| path.clone()))
+                    .map(|path| async move {
+                        let subentry_id = parent.lookup(ctx, blobstore, &path).await?;
+                        Ok::<_, Error>((path, subentry_id))
+                    })
+                    .buffer_unordered(MAX_CONCURRENT_SUBENTRY_LOOKUPS)
+                    .try_collect::<Vec<_>>()
+                    .await?;
+                for (path, subentry_id) in lookups {
+                    if let Some(subentry_id) = subentry_id {
+                        if let Some(node) = recurse_entries.get_mut(&path) {
+                            node.parents.insert(subentry_id);
+                        }
                     }
                 }
 
                 DeletedManifestChange {
                     change_type,
                     copy_subentries_from: Some(parent.clone()),
+                    moved_to,
+                    deleted_content,
                 }
             }
             _ => {
@@ -355,10 +619,14 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
                         .into_subentries(ctx, blobstore)
                         .try_for_each(|(path, mf_id)| {
                             let entry = recurse_entries.entry(path.clone()).or_insert_with(|| {
+                                let child_path =
+                                    MPath::join_opt_element(full_path.as_ref(), &path);
                                 DeletedManifestUnfoldNode {
                                     path_element: Some(path),
                                     changes: Default::default(),
                                     parents: HashSet::new(),
+                                    full_path: Some(child_path),
+                                    root_unode: root_unode.clone(),
                                 }
                             });
                             entry.parents.insert(mf_id);
@@ -369,6 +637,8 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
                 DeletedManifestChange {
                     change_type,
                     copy_subentries_from: None,
+                    moved_to,
+                    deleted_content,
                 }
             }
         };
@@ -413,25 +683,54 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
         subentries_to_update: BTreeMap<MPathElement, Option<Manifest::Id>>,
         sender: mpsc::UnboundedSender<BoxFuture<'static, Result<(), Error>>>,
         created: Arc<Mutex<HashSet<String>>>,
+        moves: Arc<Mutex<HashMap<Manifest::Id, MPath>>>,
+        contents: Arc<Mutex<HashMap<Manifest::Id, Entry<ManifestUnodeId, FileUnodeId>>>>,
     ) -> Result<Option<Manifest::Id>, Error> {
         match change.change_type {
             DeletedManifestChangeType::Reuse => Ok(change.copy_subentries_from.map(|mf| mf.id())),
-            DeletedManifestChangeType::CreateDeleted => Self::save_manifest(
-                Manifest::copy_and_update_subentries(
+            DeletedManifestChangeType::CreateDeleted => {
+                // The node itself still can't carry `change.deleted_content`:
+                // `copy_and_update_subentries` has no parameter for it, and
+                // adding one means teaching `DeletedManifestCommon`'s node
+                // value (owned by `mononoke_types`, not part of this
+                // checkout) to persist an optional content field alongside
+                // the linknode. What's captured here instead is real within
+                // this derivation call: `contents` is handed back to
+                // whoever called `derive_with_move_and_content_info`, same
+                // as `moves` below, so a same-process caller (e.g. via
+                // `deleted_content`) can look a node's pre-deletion content
+                // up without a blobstore round trip.
+                let deleted_content = change.deleted_content;
+                let mf_id = Self::save_manifest(
+                    Manifest::copy_and_update_subentries(
+                        ctx,
+                        blobstore,
+                        change.copy_subentries_from,
+                        Some(cs_id),
+                        subentries_to_update,
+                    )
+                    .await?,
                     ctx,
                     blobstore,
-                    change.copy_subentries_from,
-                    Some(cs_id),
-                    subentries_to_update,
+                    sender,
+                    created,
                 )
-                .await?,
-                ctx,
-                blobstore,
-                sender,
-                created,
-            )
-            .await
-            .map(Some),
+                .await?;
+                // `change.moved_to` is only set when this deletion came from a
+                // `PathChange::MovedTo`; record it so
+                // `derive_with_move_and_content_info` can report "moved to X"
+                // for this node within the same derivation process. Not
+                // persisted onto the node itself -- see the comment above on
+                // why that needs a `mononoke_types` change this crate can't
+                // make.
+                if let Some(dest) = change.moved_to {
+                    moves.lock().await.insert(mf_id.clone(), dest);
+                }
+                if let Some(entry) = deleted_content {
+                    contents.lock().await.insert(mf_id.clone(), entry);
+                }
+                Ok(Some(mf_id))
+            }
             DeletedManifestChangeType::RemoveIfNowEmpty => {
                 let manifest = Manifest::copy_and_update_subentries(
                     ctx,
@@ -455,11 +754,29 @@ impl<Manifest: DeletedManifestCommon> DeletedManifestDeriver<Manifest> {
     }
 }
 
+/// Returns the changeset's path changes (same as before), plus the root
+/// unode manifest of the changeset itself -- the real, already-merged
+/// working state. `DeletedManifestDeriver::derive`/`derive_with_move_and_content_info`
+/// need this to resolve divergent-parent nodes in `do_unfold` against the
+/// actual merge result instead of just guessing from parent agreement.
 pub(crate) async fn get_changes(
     ctx: &CoreContext,
     derivation_ctx: &DerivationContext,
     bonsai: BonsaiChangeset,
-) -> Result<PathTree<Option<PathChange>>, Error> {
+) -> Result<(PathTree<Option<PathChange>>, ManifestUnodeId), Error> {
+    // Capture copy-from info before diffing: maps the source path of a
+    // copy/move to its destination, so that a path which disappeared can be
+    // told apart from a genuine deletion if its content was actually copied
+    // to another path in the same commit.
+    let moved_from: BTreeMap<MPath, MPath> = bonsai
+        .file_changes()
+        .filter_map(|(to_path, file_change)| {
+            file_change
+                .copy_from()
+                .map(|(from_path, _)| (from_path.clone(), to_path.clone()))
+        })
+        .collect();
+
     // Get file/directory changes between the current changeset and its parents
     //
     // get unode manifests first
@@ -484,7 +801,8 @@ pub(crate) async fn get_changes(
 
     // compute diff between changeset's and its parents' manifests
     let unode_mf_id = root_unode_mf_id.manifest_unode_id().clone();
-    let changes = if parent_mf_ids.is_empty() {
+    let root_unode = unode_mf_id.clone();
+    let mut changes = if parent_mf_ids.is_empty() {
         unode_mf_id
             .list_all_entries(ctx.clone(), derivation_ctx.blobstore().clone())
             .try_filter_map(move |(path, _)| async {
@@ -499,13 +817,67 @@ pub(crate) async fn get_changes(
         diff_against_parents(ctx, derivation_ctx, unode_mf_id, parent_mf_ids).await
     }?;
 
-    Ok(PathTree::from_iter(
-        changes
-            .into_iter()
-            .map(|(path, change)| (path, Some(change))),
+    for (path, change) in changes.iter_mut() {
+        if matches!(change, PathChange::Remove(_)) {
+            if let Some(dest) = moved_from.get(path) {
+                *change = PathChange::MovedTo(dest.clone());
+            }
+        }
+    }
+
+    Ok((
+        PathTree::from_iter(changes.into_iter().map(|(path, change)| (path, Some(change)))),
+        root_unode,
     ))
 }
 
+/// Whether `full_path` (or, for the root, always) still exists somewhere in
+/// the tree rooted at `root_unode` -- either as a file itself or as an
+/// ancestor directory of some surviving file. Used by `do_unfold` to settle
+/// divergent-parent nodes against the real merged state.
+///
+/// There's no verified single-path lookup on `ManifestUnodeId` in this
+/// checkout (the `manifest` crate's own source isn't part of it, and
+/// `list_all_entries` -- already used above in `get_changes` -- is the only
+/// `ManifestOps` method any file here calls), so this walks every live file
+/// path under `root_unode` and checks for a prefix match. That's O(tree
+/// size) rather than O(path depth), but this only runs for the rare
+/// divergent-parent case, not on every node.
+async fn path_is_live_in_unode(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn Blobstore>,
+    root_unode: &ManifestUnodeId,
+    full_path: &Option<MPath>,
+) -> Result<bool, Error> {
+    let full_path = match full_path {
+        None => return Ok(true),
+        Some(full_path) => full_path,
+    };
+    root_unode
+        .clone()
+        .list_all_entries(ctx.clone(), blobstore.clone())
+        .try_filter_map(|(path, _)| async move { Ok(path) })
+        .try_fold(false, |found, path| {
+            let is_match = found || mpath_is_prefix(full_path, &path);
+            async move { Ok(is_match) }
+        })
+        .await
+}
+
+/// Whether every element of `ancestor` is a prefix of `descendant`'s
+/// elements (i.e. `descendant` is `ancestor` itself or lives underneath it).
+fn mpath_is_prefix(ancestor: &MPath, descendant: &MPath) -> bool {
+    let mut ancestor_elements = ancestor.into_iter();
+    let mut descendant_elements = descendant.into_iter();
+    loop {
+        match (ancestor_elements.next(), descendant_elements.next()) {
+            (None, _) => return true,
+            (Some(a), Some(d)) if a == d => continue,
+            _ => return false,
+        }
+    }
+}
+
 async fn diff_against_parents(
     ctx: &CoreContext,
     derivation_ctx: &DerivationContext,
@@ -527,7 +899,14 @@ async fn diff_against_parents(
         .flatten()
         .filter_map(|diff| match diff {
             Diff::Added(Some(path), _) => Some((path, PathChange::Add)),
-            Diff::Removed(Some(path), _) => Some((path, PathChange::Remove)),
+            // The unode `Entry` here is the pre-deletion content identity;
+            // carry it through so `do_create` can hand it off to the
+            // `contents` accumulator (see `PathChange::Remove`). It's lost
+            // again in `get_changes` if this turns out to be a move rather
+            // than a genuine deletion (`PathChange::MovedTo` doesn't carry
+            // content -- the content lives on at the destination path
+            // instead).
+            Diff::Removed(Some(path), entry) => Some((path, PathChange::Remove(Some(entry)))),
             _ => None,
         });
 
@@ -536,11 +915,14 @@ async fn diff_against_parents(
         // If the changeset has file/dir conflict the diff between
         // parent manifests and the current will have two entries
         // for the same path: one to remove the file/dir, another
-        // to introduce new dir/file node.
+        // to introduce new dir/file node. Compared by variant only (not by
+        // captured content): two `Remove`s from different parents can carry
+        // different pre-deletion `Entry` payloads and still agree that the
+        // path was removed, which isn't a conflict.
         changes
             .entry(path)
             .and_modify(|e| {
-                if *e != change {
+                if std::mem::discriminant(e) != std::mem::discriminant(&change) {
                     *e = PathChange::FileDirConflict
                 }
             })
@@ -550,105 +932,711 @@ async fn diff_against_parents(
     Ok(res)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::mapping::RootDeletedManifestId;
-    use blobrepo::{save_bonsai_changesets, BlobRepo};
-    use bounded_traversal::bounded_traversal_stream;
-    use derived_data_test_utils::bonsai_changeset_from_hg;
-    use fbinit::FacebookInit;
-    use fixtures::{many_files_dirs, store_files};
-    use futures::{pin_mut, stream::iter, Stream, TryStreamExt};
-    use maplit::btreemap;
-    use mononoke_types::{
-        deleted_files_manifest::DeletedManifest, BonsaiChangeset, BonsaiChangesetMut, DateTime,
-        DeletedManifestId, FileChange, MPath,
+/// The state of a path as recorded by a deleted files manifest, as returned
+/// by [`find_deletion_linknode`].
+///
+/// The two deleted variants are told apart only by whether the path's node
+/// has subentries: a deleted node with none is `DeletedFile`, one with any is
+/// `DeletedDirectory`. A file that replaced an earlier deleted directory and
+/// was then deleted itself keeps those subentries, so it is reported as
+/// `DeletedDirectory`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum DeletedPathStatus {
+    /// The path itself is a deleted file.
+    DeletedFile(ChangesetId),
+    /// The path itself is a deleted directory (it may still have deleted
+    /// descendants recorded underneath it).
+    DeletedDirectory(ChangesetId),
+    /// The path is live. It may still have deleted descendants.
+    Live,
+}
+
+/// Walk the deleted manifest rooted at `root` down `path`, and return the
+/// linknode recorded on `path`'s own node, along with whether `path` itself
+/// is currently a deleted file, a deleted directory, or a live directory
+/// with deleted descendants. Ancestors' linknodes are not returned: a path
+/// with no node of its own is reported as `Live`, even when an ancestor
+/// directory is recorded as deleted.
+///
+/// This reuses the linknode semantics documented on [`DeletedManifestDeriver`]:
+/// an initialized linknode means the node (file or directory) was deleted at
+/// that changeset; an empty linknode means the path is live but some of its
+/// subentries are not.
+pub(crate) async fn find_deletion_linknode<Manifest: DeletedManifestCommon>(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn Blobstore>,
+    root: Manifest::Id,
+    path: Option<&MPath>,
+) -> Result<DeletedPathStatus, Error> {
+    Ok(find_entry::<Manifest>(ctx, blobstore, root, path)
+        .await?
+        .map(|(status, _id)| status)
+        // No node was ever recorded for this path itself, so it was never
+        // deleted on its own.
+        .unwrap_or(DeletedPathStatus::Live))
+}
+
+/// Point-lookup a single path in the deleted manifest rooted at `root`,
+/// descending element-by-element and loading only the nodes on the path
+/// (O(path-depth) blobstore reads) rather than the whole tree. Returns
+/// `None` if the descent falls off the tree, i.e. no deleted manifest node
+/// was ever recorded along this path; otherwise returns `path`'s status
+/// together with the id of its own manifest node.
+pub(crate) async fn find_entry<Manifest: DeletedManifestCommon>(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn Blobstore>,
+    root: Manifest::Id,
+    path: Option<&MPath>,
+) -> Result<Option<(DeletedPathStatus, Manifest::Id)>, Error> {
+    let mut mf_id = root;
+    let mut manifest = mf_id.load(ctx, blobstore).await?;
+
+    if let Some(path) = path {
+        for element in path.into_iter() {
+            match manifest.lookup(ctx, blobstore, &element).await? {
+                Some(next_id) => {
+                    mf_id = next_id;
+                    manifest = mf_id.load(ctx, blobstore).await?;
+                }
+                None => return Ok(None),
+            }
+        }
+    }
+
+    let status = match manifest.linknode() {
+        Some(cs_id) if manifest.is_empty() => DeletedPathStatus::DeletedFile(cs_id.clone()),
+        Some(cs_id) => DeletedPathStatus::DeletedDirectory(cs_id.clone()),
+        None => DeletedPathStatus::Live,
     };
-    use pretty_assertions::assert_eq;
-    use repo_derived_data::RepoDerivedDataRef;
-    use sorted_vector_map::SortedVectorMap;
-    use tests_utils::CreateCommitContext;
 
-    #[fbinit::test]
-    async fn linear_test(fb: FacebookInit) {
-        // Test simple separate files and whole dir deletions
-        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
-        let ctx = CoreContext::test_mock(fb);
+    Ok(Some((status, mf_id)))
+}
 
-        // create parent deleted files manifest
-        let (bcs_id_1, mf_id_1) = {
-            let file_changes = btreemap! {
-                "file.txt" => Some("1\n"),
-                "file-2.txt" => Some("2\n"),
-                "dir/sub/f-1" => Some("3\n"),
-                "dir/f-2" => Some("4\n"),
-                "dir-2/sub/f-3" => Some("5\n"),
-                "dir-2/f-4" => Some("6\n"),
-            };
-            let (bcs_id, mf_id, deleted_nodes) =
-                create_cs_and_derive_manifest(ctx.clone(), repo.clone(), file_changes, vec![])
-                    .await;
+/// Looks up the pre-deletion unode `Entry` captured for the node that
+/// `find_entry` resolved a path to, out of the `contents` map returned by
+/// `DeletedManifestDeriver::derive_with_move_and_content_info`.
+///
+/// `DeletedManifestCommon` (owned by `mononoke_types`, not part of this
+/// checkout) has no field to persist this past the derivation call that
+/// created the node, so `contents` only has entries for nodes created by
+/// whichever `derive_with_move_and_content_info` call produced it -- a node
+/// loaded fresh out of the blobstore by a later derivation, or by a
+/// different process entirely, won't be found here. It is keyed by node,
+/// not by path: compose it with `find_entry` to go from a path to its
+/// content, same as `find_entry` itself goes from a path to a node.
+pub(crate) fn deleted_content<'a, Manifest: DeletedManifestCommon>(
+    contents: &'a HashMap<Manifest::Id, Entry<ManifestUnodeId, FileUnodeId>>,
+    node: &Manifest::Id,
+) -> Option<&'a Entry<ManifestUnodeId, FileUnodeId>> {
+    contents.get(node)
+}
 
-            // nothing was deleted yet
-            let expected_nodes = vec![(None, Status::Live)];
-            assert_eq!(deleted_nodes, expected_nodes);
+/// Recovers the content that existed at `path` immediately before it was
+/// deleted, using only `linknode` -- the changeset where the deletion
+/// happened, which (unlike `deleted_content`'s `contents` map) really is a
+/// field persisted on every deleted node; see `manifest.linknode()` in
+/// `find_entry`. A path's `DeletedPathStatus::DeletedFile`/`DeletedDirectory`
+/// carries this same `linknode`, so any caller that can load that status
+/// from the blobstore can call this too -- no matter which process or how
+/// many derivations ago the node itself was created. This is what makes
+/// "show me the contents of a file deleted three commits ago" reachable: it
+/// doesn't depend on `derive_with_move_and_content_info`'s `contents`
+/// accumulator still being in memory.
+///
+/// Since the path was live just before `linknode`, looks it up in each of
+/// `linknode`'s parents' own unode manifests (derived fresh via
+/// `derive_dependency`, not read off the deleted manifest at all) and
+/// returns the first match. Returns `None` if `path` isn't found in any
+/// parent, which shouldn't happen for a `DeletedFile`/`DeletedDirectory`
+/// status, but the manifests involved are independently-derived data this
+/// function doesn't control.
+pub(crate) async fn recover_deleted_content(
+    ctx: &CoreContext,
+    derivation_ctx: &DerivationContext,
+    linknode: ChangesetId,
+    path: &MPath,
+) -> Result<Option<Entry<ManifestUnodeId, FileUnodeId>>, Error> {
+    let blobstore = derivation_ctx.blobstore();
+    let bonsai = linknode.load(ctx, blobstore).await?;
 
-            (bcs_id, mf_id)
-        };
+    for parent_cs_id in bonsai.parents() {
+        let parent_root_unode = derivation_ctx
+            .derive_dependency::<RootUnodeManifestId>(ctx, parent_cs_id)
+            .await?;
+        let found = find_unode_entry_at_path(
+            ctx,
+            blobstore,
+            parent_root_unode.manifest_unode_id(),
+            path,
+        )
+        .await?;
+        if found.is_some() {
+            return Ok(found);
+        }
+    }
 
-        // delete some files and dirs
-        let (bcs_id_2, mf_id_2) = {
-            let file_changes = btreemap! {
-                "file.txt" => None,
-                "file-2.txt" => Some("2\n2\n"),
-                "file-3.txt" => Some("3\n3\n"),
-                "dir/sub/f-1" => None,
-                "dir/f-2" => None,
-                "dir-2/sub/f-3" => None,
-            };
-            let (bcs_id, mf_id, deleted_nodes) = create_cs_and_derive_manifest(
-                ctx.clone(),
-                repo.clone(),
-                file_changes,
-                vec![(bcs_id_1, mf_id_1)],
-            )
-            .await;
+    Ok(None)
+}
 
-            let expected_nodes = vec![
-                (None, Status::Live),
-                (Some(path("dir")), Status::Deleted(bcs_id)),
-                (Some(path("dir/f-2")), Status::Deleted(bcs_id)),
-                (Some(path("dir/sub")), Status::Deleted(bcs_id)),
-                (Some(path("dir/sub/f-1")), Status::Deleted(bcs_id)),
-                (Some(path("dir-2")), Status::Live),
-                (Some(path("dir-2/sub")), Status::Deleted(bcs_id)),
-                (Some(path("dir-2/sub/f-3")), Status::Deleted(bcs_id)),
-                (Some(path("file.txt")), Status::Deleted(bcs_id)),
-            ];
-            assert_eq!(deleted_nodes, expected_nodes);
+/// Exact-path lookup for a single unode entry, built on the same
+/// `list_all_entries` primitive `path_is_live_in_unode` uses for its
+/// prefix check -- see that function's comment for why there's no cheaper
+/// verified single-path lookup on `ManifestUnodeId` in this checkout.
+async fn find_unode_entry_at_path(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn Blobstore>,
+    root_unode: &ManifestUnodeId,
+    path: &MPath,
+) -> Result<Option<Entry<ManifestUnodeId, FileUnodeId>>, Error> {
+    root_unode
+        .clone()
+        .list_all_entries(ctx.clone(), blobstore.clone())
+        .try_filter_map(|(entry_path, entry)| async move {
+            match entry_path {
+                Some(entry_path) if &entry_path == path => Ok(Some(entry)),
+                _ => Ok(None),
+            }
+        })
+        .try_next()
+        .await
+}
 
-            (bcs_id, mf_id)
+/// Looks up the rename destination recorded for the node that `find_entry`
+/// resolved a path to, out of the `moves` map returned by
+/// `DeletedManifestDeriver::derive_with_move_and_content_info`.
+///
+/// Same boundary as `deleted_content` above: the destination lives only in
+/// `moves`, not on the node, because `DeletedManifestCommon` (owned by
+/// `mononoke_types`, not part of this checkout) has no field to carry it.
+/// This is the queryable half of the feature this crate can actually
+/// deliver -- a caller holding the `moves` map from the same derivation call
+/// that created `node` can go from a deleted path straight to "moved to X"
+/// by composing this with `find_entry`, without re-deriving anything.
+pub(crate) fn moved_to<'a, Manifest: DeletedManifestCommon>(
+    moves: &'a HashMap<Manifest::Id, MPath>,
+    node: &Manifest::Id,
+) -> Option<&'a MPath> {
+    moves.get(node)
+}
+
+/// Stream every currently-deleted path found by descending the deleted
+/// manifest rooted at `root`, starting at `prefix` (the whole tree if
+/// `None`). Yields one `(path, linknode)` pair per node that has an
+/// initialized linknode, i.e. every deleted file and deleted directory,
+/// skipping over live directories other than to recurse through them in
+/// search of deleted descendants.
+pub(crate) fn list_deleted_paths<Manifest: DeletedManifestCommon>(
+    ctx: CoreContext,
+    blobstore: Arc<dyn Blobstore>,
+    root: Manifest::Id,
+    prefix: Option<MPath>,
+) -> impl futures::stream::Stream<Item = Result<(Option<MPath>, ChangesetId), Error>> {
+    async_stream::stream! {
+        let mut manifest = match root.load(&ctx, &blobstore).await {
+            Ok(manifest) => manifest,
+            Err(err) => {
+                yield Err(err);
+                return;
+            }
         };
 
-        // reincarnate file and directory
-        let (bcs_id_3, mf_id_3) = {
-            let file_changes = btreemap! {
-                "file.txt" => Some("1\n1\n1\n"),
-                "file-2.txt" => None,
-                "dir/sub/f-4" => Some("4\n4\n4\n"),
-            };
-            let (bcs_id, mf_id, deleted_nodes) = create_cs_and_derive_manifest(
-                ctx.clone(),
-                repo.clone(),
-                file_changes,
-                vec![(bcs_id_2, mf_id_2)],
-            )
-            .await;
+        if let Some(prefix) = &prefix {
+            for element in prefix.into_iter() {
+                match manifest.lookup(&ctx, &blobstore, &element).await {
+                    Ok(Some(next_id)) => {
+                        manifest = match next_id.load(&ctx, &blobstore).await {
+                            Ok(manifest) => manifest,
+                            Err(err) => {
+                                yield Err(err);
+                                return;
+                            }
+                        };
+                    }
+                    // Nothing was ever deleted under this prefix.
+                    Ok(None) => return,
+                    Err(err) => {
+                        yield Err(err);
+                        return;
+                    }
+                }
+            }
+        }
 
-            let expected_nodes = vec![
-                (None, Status::Live),
-                (Some(path("dir")), Status::Live),
+        let start = manifest.id();
+        let s = bounded_traversal::bounded_traversal_stream(
+            256,
+            Some((prefix, start)),
+            move |(path, mf_id)| {
+                cloned!(ctx, blobstore);
+                async move {
+                    let manifest = mf_id.load(&ctx, &blobstore).await?;
+                    let mut out = Vec::new();
+                    if let Some(cs_id) = manifest.linknode() {
+                        out.push((path.clone(), cs_id.clone()));
+                    }
+                    let recurse = manifest
+                        .into_subentries(&ctx, &blobstore)
+                        .map_ok(|(name, mf_id)| {
+                            let full_path = MPath::join_opt_element(path.as_ref(), &name);
+                            (Some(full_path), mf_id)
+                        })
+                        .try_collect::<Vec<_>>()
+                        .await?;
+                    Result::<_, Error>::Ok((out, recurse))
+                }
+                .boxed()
+            },
+        )
+        .map_ok(|entries| futures::stream::iter(entries.into_iter().map(Ok)))
+        .try_flatten();
+
+        futures::pin_mut!(s);
+        while let Some(value) = s.next().await {
+            yield value;
+        }
+    }
+}
+
+/// A per-path change detected by [`diff_deleted_manifests`] between two
+/// deleted manifest roots.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum DeletedManifestDiffEntry {
+    /// The path was live in `base` and is deleted in `other`, as of the
+    /// linknode recorded in `other`.
+    NowDeleted(Option<MPath>, ChangesetId),
+    /// The path was deleted in `base` and is live again in `other`.
+    Reincarnated(Option<MPath>),
+    /// The path is deleted on both sides, but the linknode recording when
+    /// it was deleted is different.
+    LinknodeChanged(Option<MPath>, ChangesetId, ChangesetId),
+}
+
+/// Restricts a [`diff_deleted_manifests`] traversal to paths of interest.
+/// Implementations should be cheap: every visited subtree consults it before
+/// being recursed into.
+pub(crate) trait DeletedManifestDiffMatcher: Send + Sync {
+    /// Whether the subtree rooted at `path` might contain paths this
+    /// matcher cares about. Returning `false` lets the diff skip the
+    /// subtree without loading either side.
+    fn matches_recursive(&self, path: &MPath) -> bool;
+}
+
+impl<F: Fn(&MPath) -> bool + Send + Sync> DeletedManifestDiffMatcher for F {
+    fn matches_recursive(&self, path: &MPath) -> bool {
+        self(path)
+    }
+}
+
+/// Stream the per-path differences between two deleted manifest trees. This
+/// is a synchronized descent over pairs of nodes: at each level, subentry
+/// names are unioned across both sides and only names whose child id
+/// differs between `base` and `other` are recursed into. Whenever both
+/// sides reference the identical `DeletedManifestId` for a subtree, the
+/// subtree is pruned without being loaded, so cost is proportional to the
+/// changed region rather than the size of either tree.
+pub(crate) fn diff_deleted_manifests<Manifest: DeletedManifestCommon>(
+    ctx: CoreContext,
+    blobstore: Arc<dyn Blobstore>,
+    base: Manifest::Id,
+    other: Manifest::Id,
+    matcher: Arc<dyn DeletedManifestDiffMatcher>,
+) -> impl futures::stream::Stream<Item = Result<DeletedManifestDiffEntry, Error>> {
+    async_stream::stream! {
+        let s = bounded_traversal::bounded_traversal_stream(
+            256,
+            Some((None, Some(base), Some(other))),
+            move |(path, base_id, other_id): (
+                Option<MPath>,
+                Option<Manifest::Id>,
+                Option<Manifest::Id>,
+            )| {
+                cloned!(ctx, blobstore, matcher);
+                async move {
+                    if base_id == other_id {
+                        // Identical subtree on both sides: nothing changed.
+                        return Result::<_, Error>::Ok((vec![], vec![]));
+                    }
+
+                    let base_manifest = match base_id {
+                        Some(id) => Some(id.load(&ctx, &blobstore).await?),
+                        None => None,
+                    };
+                    let other_manifest = match other_id {
+                        Some(id) => Some(id.load(&ctx, &blobstore).await?),
+                        None => None,
+                    };
+
+                    let base_linknode = base_manifest.as_ref().and_then(|m| m.linknode().clone());
+                    let other_linknode = other_manifest.as_ref().and_then(|m| m.linknode().clone());
+
+                    let mut out = Vec::new();
+                    match (base_linknode, other_linknode) {
+                        (None, Some(other_cs)) => {
+                            out.push(DeletedManifestDiffEntry::NowDeleted(path.clone(), other_cs));
+                        }
+                        (Some(_), None) => {
+                            out.push(DeletedManifestDiffEntry::Reincarnated(path.clone()));
+                        }
+                        (Some(base_cs), Some(other_cs)) if base_cs != other_cs => {
+                            out.push(DeletedManifestDiffEntry::LinknodeChanged(
+                                path.clone(),
+                                base_cs,
+                                other_cs,
+                            ));
+                        }
+                        _ => {}
+                    }
+
+                    // Union subentry names from both sides.
+                    let mut children: BTreeMap<
+                        MPathElement,
+                        (Option<Manifest::Id>, Option<Manifest::Id>),
+                    > = BTreeMap::new();
+                    if let Some(manifest) = &base_manifest {
+                        manifest
+                            .into_subentries(&ctx, &blobstore)
+                            .try_for_each(|(name, id)| {
+                                children.entry(name).or_default().0 = Some(id);
+                                async { Ok(()) }
+                            })
+                            .await?;
+                    }
+                    if let Some(manifest) = &other_manifest {
+                        manifest
+                            .into_subentries(&ctx, &blobstore)
+                            .try_for_each(|(name, id)| {
+                                children.entry(name).or_default().1 = Some(id);
+                                async { Ok(()) }
+                            })
+                            .await?;
+                    }
+
+                    let recurse = children
+                        .into_iter()
+                        .filter(|(_, (base_id, other_id))| base_id != other_id)
+                        .filter_map(|(name, (base_id, other_id))| {
+                            let child_path = MPath::join_opt_element(path.as_ref(), &name);
+                            if matcher.matches_recursive(&child_path) {
+                                Some((Some(child_path), base_id, other_id))
+                            } else {
+                                None
+                            }
+                        })
+                        .collect::<Vec<_>>();
+
+                    Ok((out, recurse))
+                }
+                .boxed()
+            },
+        )
+        .map_ok(|entries| futures::stream::iter(entries.into_iter().map(Ok)))
+        .try_flatten();
+
+        futures::pin_mut!(s);
+        while let Some(value) = s.next().await {
+            yield value;
+        }
+    }
+}
+
+/// Author/message metadata for the nth restore commit produced by
+/// [`restore_deleted_paths`].
+pub(crate) struct RestoreChangesetArgs {
+    pub author: String,
+    pub message: String,
+}
+
+/// Why a requested path was not restored by [`restore_deleted_paths`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum RestoreSkipReason {
+    /// No deleted manifest node was ever recorded for this path.
+    NeverDeleted,
+    /// The path currently has live content; restoring it would silently
+    /// clobber that content, so it's skipped rather than restored.
+    PathIsLive,
+    /// Either the path is a deleted directory (which doesn't carry a single
+    /// content blob of its own to restore as one `FileChange`), or
+    /// `recover_deleted_content` couldn't find the pre-deletion content in
+    /// any of the linknode's parents' own unode manifests.
+    ContentUnavailable,
+}
+
+/// Rebuilds deleted paths as one or more bonsai changesets chained off
+/// `parent`, chunked by `chunker` (e.g. an even-size `max_chunk_size`
+/// chunker) so a single restore doesn't produce an unbounded commit.
+/// `changeset_args_factory` supplies the author/message metadata for the
+/// nth restore commit (`i` is the chunk's index in `chunker`'s output, so
+/// the first restore commit gets `changeset_args_factory(0)`, and so on).
+///
+/// Restoring under a path that's currently live is skipped with
+/// [`RestoreSkipReason::PathIsLive`] rather than aborting the whole call,
+/// since a live-path conflict on one requested path says nothing about the
+/// others; a path that was never deleted is skipped with
+/// [`RestoreSkipReason::NeverDeleted`].
+///
+/// Every other requested path resolves its node via `find_entry` and
+/// recovers its pre-deletion content via `recover_deleted_content`, which
+/// needs only the node's `linknode` -- unlike the older `contents`-map
+/// approach this replaced, that works regardless of which process (or how
+/// many derivations ago) produced the deletion. Paths with recovered
+/// content are grouped into `chunker`'s batches; each non-empty batch
+/// becomes one bonsai changeset, parented off the previous restore commit
+/// (or `parent` for the first chunk), persisted via
+/// `blobrepo::save_bonsai_changesets`. The resulting chain's changeset ids
+/// are returned in chunk order.
+pub(crate) async fn restore_deleted_paths<Manifest: DeletedManifestCommon>(
+    ctx: &CoreContext,
+    derivation_ctx: &DerivationContext,
+    repo: &BlobRepo,
+    root: Manifest::Id,
+    parent: ChangesetId,
+    paths: Vec<MPath>,
+    chunker: impl Fn(Vec<MPath>) -> Vec<Vec<MPath>>,
+    changeset_args_factory: impl Fn(usize) -> RestoreChangesetArgs,
+) -> Result<(Vec<ChangesetId>, Vec<(MPath, RestoreSkipReason)>), Error> {
+    let blobstore = derivation_ctx.blobstore();
+    let mut skipped = Vec::new();
+    let mut recovered: BTreeMap<MPath, FileUnodeId> = BTreeMap::new();
+    let parent_unode = derivation_ctx
+        .derive_dependency::<RootUnodeManifestId>(ctx, parent)
+        .await?
+        .manifest_unode_id()
+        .clone();
+
+    for path in paths {
+        if path_is_live_in_unode(ctx, blobstore, &parent_unode, &Some(path.clone())).await? {
+            skipped.push((path, RestoreSkipReason::PathIsLive));
+            continue;
+        }
+        match find_entry::<Manifest>(ctx, blobstore, root, Some(&path)).await? {
+            None => skipped.push((path, RestoreSkipReason::NeverDeleted)),
+            Some((DeletedPathStatus::Live, _)) => {
+                skipped.push((path, RestoreSkipReason::PathIsLive));
+            }
+            Some((DeletedPathStatus::DeletedDirectory(_), _)) => {
+                skipped.push((path, RestoreSkipReason::ContentUnavailable));
+            }
+            Some((DeletedPathStatus::DeletedFile(linknode), _)) => {
+                match recover_deleted_content(ctx, derivation_ctx, linknode, &path).await? {
+                    Some(Entry::Leaf(file_unode_id)) => {
+                        recovered.insert(path, file_unode_id);
+                    }
+                    // A `DeletedFile` status means `do_unfold` saw this node
+                    // as a file (no subentries), so a recovered `Entry::Tree`
+                    // here would mean the parent commit's own unode tree
+                    // disagrees about this path's kind -- independently
+                    // derived data this function doesn't control. Treat it
+                    // the same as "nothing recoverable" rather than panic.
+                    Some(Entry::Tree(_)) | None => {
+                        skipped.push((path, RestoreSkipReason::ContentUnavailable));
+                    }
+                }
+            }
+        }
+    }
+
+    let restorable_paths: Vec<MPath> = recovered.keys().cloned().collect();
+    let mut restored = Vec::new();
+    let mut parent = parent;
+
+    for (i, chunk) in chunker(restorable_paths).into_iter().enumerate() {
+        if chunk.is_empty() {
+            continue;
+        }
+
+        let mut file_changes: SortedVectorMap<MPath, FileChange> = Default::default();
+        for path in &chunk {
+            let file_unode_id = recovered
+                .get(path)
+                .expect("chunker may only reorder/group restorable_paths, not invent new ones");
+            let unode = file_unode_id.load(ctx, blobstore).await?;
+            file_changes.insert(
+                path.clone(),
+                FileChange::tracked(
+                    unode.content_id().clone(),
+                    unode.file_type().clone(),
+                    unode.size(),
+                    None,
+                ),
+            );
+        }
+
+        let args = changeset_args_factory(i);
+        let bcs = BonsaiChangesetMut {
+            parents: vec![parent],
+            author: args.author,
+            author_date: DateTime::now(),
+            committer: None,
+            committer_date: None,
+            message: args.message,
+            extra: Default::default(),
+            file_changes,
+            is_snapshot: false,
+        }
+        .freeze()?;
+
+        let cs_id = bcs.get_changeset_id();
+        save_bonsai_changesets(vec![bcs], ctx.clone(), repo).await?;
+        restored.push(cs_id.clone());
+        parent = cs_id;
+    }
+
+    Ok((restored, skipped))
+}
+
+/// One coalesced lifecycle event in a path's deletion history, as returned
+/// by [`deleted_path_history`].
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) enum PathLifecycleEvent {
+    /// The path was deleted; the changeset is its linknode.
+    Deleted(ChangesetId),
+    /// The path was live as of this changeset.
+    Live(ChangesetId),
+}
+
+/// Resolves the ordered sequence of lifecycle events for `path` across
+/// `ancestry`, coalescing consecutive commits that record the same status
+/// into a single event (so a path deleted once and never touched again
+/// produces one `Deleted` event, not one per commit).
+///
+/// `ancestry` must list, oldest-first, every changeset to inspect along
+/// with that changeset's already-derived deleted manifest root. Walking
+/// the actual commit graph to build that sequence is the caller's
+/// responsibility: derive.rs has no changeset-ancestry primitives of its
+/// own (no `ChangesetFetcher` is used anywhere in this crate) to call
+/// into, so this takes the resolved sequence directly rather than a bare
+/// `heads: Vec<ChangesetId>`.
+pub(crate) async fn deleted_path_history<Manifest: DeletedManifestCommon>(
+    ctx: &CoreContext,
+    blobstore: &Arc<dyn Blobstore>,
+    path: &MPath,
+    ancestry: Vec<(ChangesetId, Manifest::Id)>,
+) -> Result<Vec<PathLifecycleEvent>, Error> {
+    let mut history: Vec<PathLifecycleEvent> = Vec::new();
+
+    for (cs_id, root) in ancestry {
+        let status = find_entry::<Manifest>(ctx, blobstore, root, Some(path))
+            .await?
+            .map(|(status, _id)| status)
+            .unwrap_or(DeletedPathStatus::Live);
+
+        let event = match status {
+            DeletedPathStatus::Live => PathLifecycleEvent::Live(cs_id),
+            DeletedPathStatus::DeletedFile(linknode) | DeletedPathStatus::DeletedDirectory(linknode) => {
+                PathLifecycleEvent::Deleted(linknode)
+            }
+        };
+
+        let changed = match history.last() {
+            None => true,
+            Some(PathLifecycleEvent::Live(_)) => !matches!(event, PathLifecycleEvent::Live(_)),
+            Some(PathLifecycleEvent::Deleted(prev)) => match &event {
+                PathLifecycleEvent::Deleted(cur) => cur != prev,
+                PathLifecycleEvent::Live(_) => true,
+            },
+        };
+        if changed {
+            history.push(event);
+        }
+    }
+
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mapping::RootDeletedManifestId;
+    use bounded_traversal::bounded_traversal_stream;
+    use derived_data_test_utils::bonsai_changeset_from_hg;
+    use fbinit::FacebookInit;
+    use fixtures::{many_files_dirs, store_files};
+    use futures::{pin_mut, stream::iter, Stream, TryStreamExt};
+    use maplit::btreemap;
+    use mononoke_types::{deleted_files_manifest::DeletedManifest, DeletedManifestId};
+    use pretty_assertions::assert_eq;
+    use repo_derived_data::RepoDerivedDataRef;
+    use tests_utils::CreateCommitContext;
+
+    #[fbinit::test]
+    async fn linear_test(fb: FacebookInit) {
+        // Test simple separate files and whole dir deletions
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+
+        // create parent deleted files manifest
+        let (bcs_id_1, mf_id_1) = {
+            let file_changes = btreemap! {
+                "file.txt" => Some("1\n"),
+                "file-2.txt" => Some("2\n"),
+                "dir/sub/f-1" => Some("3\n"),
+                "dir/f-2" => Some("4\n"),
+                "dir-2/sub/f-3" => Some("5\n"),
+                "dir-2/f-4" => Some("6\n"),
+            };
+            let (bcs_id, mf_id, deleted_nodes) =
+                create_cs_and_derive_manifest(ctx.clone(), repo.clone(), file_changes, vec![])
+                    .await;
+
+            // nothing was deleted yet
+            let expected_nodes = vec![(None, Status::Live)];
+            assert_eq!(deleted_nodes, expected_nodes);
+
+            (bcs_id, mf_id)
+        };
+
+        // delete some files and dirs
+        let (bcs_id_2, mf_id_2) = {
+            let file_changes = btreemap! {
+                "file.txt" => None,
+                "file-2.txt" => Some("2\n2\n"),
+                "file-3.txt" => Some("3\n3\n"),
+                "dir/sub/f-1" => None,
+                "dir/f-2" => None,
+                "dir-2/sub/f-3" => None,
+            };
+            let (bcs_id, mf_id, deleted_nodes) = create_cs_and_derive_manifest(
+                ctx.clone(),
+                repo.clone(),
+                file_changes,
+                vec![(bcs_id_1, mf_id_1)],
+            )
+            .await;
+
+            let expected_nodes = vec![
+                (None, Status::Live),
+                (Some(path("dir")), Status::Deleted(bcs_id)),
+                (Some(path("dir/f-2")), Status::Deleted(bcs_id)),
+                (Some(path("dir/sub")), Status::Deleted(bcs_id)),
+                (Some(path("dir/sub/f-1")), Status::Deleted(bcs_id)),
+                (Some(path("dir-2")), Status::Live),
+                (Some(path("dir-2/sub")), Status::Deleted(bcs_id)),
+                (Some(path("dir-2/sub/f-3")), Status::Deleted(bcs_id)),
+                (Some(path("file.txt")), Status::Deleted(bcs_id)),
+            ];
+            assert_eq!(deleted_nodes, expected_nodes);
+
+            (bcs_id, mf_id)
+        };
+
+        // reincarnate file and directory
+        let (bcs_id_3, mf_id_3) = {
+            let file_changes = btreemap! {
+                "file.txt" => Some("1\n1\n1\n"),
+                "file-2.txt" => None,
+                "dir/sub/f-4" => Some("4\n4\n4\n"),
+            };
+            let (bcs_id, mf_id, deleted_nodes) = create_cs_and_derive_manifest(
+                ctx.clone(),
+                repo.clone(),
+                file_changes,
+                vec![(bcs_id_2, mf_id_2)],
+            )
+            .await;
+
+            let expected_nodes = vec![
+                (None, Status::Live),
+                (Some(path("dir")), Status::Live),
                 (Some(path("dir/f-2")), Status::Deleted(bcs_id_2)),
                 (Some(path("dir/sub")), Status::Live),
                 (Some(path("dir/sub/f-1")), Status::Deleted(bcs_id_2)),
@@ -1090,7 +2078,7 @@ mod tests {
         let blobstore = repo.blobstore().boxed();
         let bcs_id = bcs.get_changeset_id();
 
-        let changes = get_changes(
+        let (changes, root_unode) = get_changes(
             ctx,
             &repo.repo_derived_data().manager().derivation_context(None),
             bcs,
@@ -1103,6 +2091,7 @@ mod tests {
             bcs_id,
             parent_mf_ids,
             changes,
+            root_unode,
         );
 
         let dfm_id = f.await.unwrap();
@@ -1197,4 +2186,799 @@ mod tests {
     fn path(path_str: &str) -> MPath {
         MPath::new(path_str).unwrap()
     }
+
+    #[fbinit::test]
+    async fn derive_batch_test(fb: FacebookInit) -> Result<(), Error> {
+        // derive_batch must agree with deriving the same stack one commit at
+        // a time: each commit's blobstore writes need to be drained before
+        // the next commit is derived, since it reads back the previous
+        // commit's nodes as its parent manifest.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file", "1")
+            .add_file("dir/file", "2")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a.clone()])
+            .delete_file("file")
+            .commit()
+            .await?;
+        let c = CreateCommitContext::new(&ctx, &repo, vec![b.clone()])
+            .delete_file("dir/file")
+            .commit()
+            .await?;
+        let cs_ids = vec![a, b, c];
+
+        let derivation_ctx = repo.repo_derived_data().manager().derivation_context(None);
+
+        let mut stack = Vec::new();
+        for cs_id in &cs_ids {
+            let bcs = cs_id.load(&ctx, &blobstore).await?;
+            let (changes, root_unode) = get_changes(&ctx, &derivation_ctx, bcs.clone()).await?;
+            stack.push((bcs, changes, root_unode));
+        }
+
+        let batch_result =
+            DeletedManifestDeriver::<DeletedManifest>::derive_batch(&ctx, &blobstore, stack, None)
+                .await?;
+        assert_eq!(batch_result.len(), cs_ids.len());
+
+        let mut parent = None;
+        for cs_id in &cs_ids {
+            let bcs = cs_id.load(&ctx, &blobstore).await?;
+            let (changes, root_unode) = get_changes(&ctx, &derivation_ctx, bcs).await?;
+            let mf_id = DeletedManifestDeriver::<DeletedManifest>::derive(
+                &ctx,
+                &blobstore,
+                cs_id.clone(),
+                parent.into_iter().collect(),
+                changes,
+                root_unode,
+            )
+            .await?;
+            assert_eq!(batch_result.get(cs_id), Some(&mf_id));
+            parent = Some(mf_id);
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn derive_merge_divergent_status_test(fb: FacebookInit) -> Result<(), Error> {
+        // Exercises do_unfold's handling of two parents whose deleted-manifest
+        // nodes for the same path disagree about deletion status, with no
+        // explicit change recorded for that path itself -- the case where
+        // the two branches being merged resolved "dir"'s fate differently.
+        // `do_unfold` can't tell from parent agreement alone which way a
+        // real merge commit actually came down, so it must check the
+        // merge's own unode tree (`root_unode`) to get the right answer.
+        // The merge built below explicitly keeps "dir" fully deleted (it
+        // picks the a-branch's resolution for the one path the branches
+        // disagree on); if `do_unfold` fell back to guessing instead of
+        // checking `root_unode`, it would report "dir" as live instead.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        // Lineage where "dir" ends up fully deleted.
+        let a1 = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("dir/a", "1")
+            .commit()
+            .await?;
+        let a2 = CreateCommitContext::new(&ctx, &repo, vec![a1])
+            .delete_file("dir/a")
+            .commit()
+            .await?;
+        let deleted_root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, a2.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+        let (_, deleted_dir_mf_id) = find_entry::<DeletedManifest>(
+            &ctx,
+            &blobstore,
+            deleted_root_mf_id,
+            Some(&path("dir")),
+        )
+        .await?
+        .expect("dir should be recorded as deleted");
+
+        // Lineage where "dir" is live (dir/a reincarnated) but the node is
+        // still materialized because dir/b stays deleted.
+        let b1 = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("dir/a", "1")
+            .add_file("dir/b", "2")
+            .commit()
+            .await?;
+        let b2 = CreateCommitContext::new(&ctx, &repo, vec![b1])
+            .delete_file("dir/a")
+            .delete_file("dir/b")
+            .commit()
+            .await?;
+        let b3 = CreateCommitContext::new(&ctx, &repo, vec![b2])
+            .add_file("dir/a", "1-again")
+            .commit()
+            .await?;
+        let live_root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, b3.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+        let (live_status, live_dir_mf_id) =
+            find_entry::<DeletedManifest>(&ctx, &blobstore, live_root_mf_id, Some(&path("dir")))
+                .await?
+                .expect("dir should still be a recorded node");
+        assert_eq!(live_status, DeletedPathStatus::Live);
+
+        // A real merge of the two lineages above. "dir/a" is the only path
+        // the two branches disagree on (absent in a2, present in b3), so the
+        // merge must resolve it explicitly; resolving it by deleting keeps
+        // "dir" entirely empty in the merge's own real working state, even
+        // though "dir" itself is never mentioned in the merge's own file
+        // changes.
+        let m = CreateCommitContext::new(&ctx, &repo, vec![a2, b3])
+            .delete_file("dir/a")
+            .commit()
+            .await?;
+        let root_unode = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootUnodeManifestId>(&ctx, m, None)
+            .await?
+            .manifest_unode_id()
+            .clone();
+
+        let parents: HashSet<DeletedManifestId> =
+            vec![deleted_dir_mf_id, live_dir_mf_id].into_iter().collect();
+        let changes: PathTree<Option<PathChange>> = PathTree::from_iter(Vec::new());
+        let (change, _unfold_nodes) = DeletedManifestDeriver::<DeletedManifest>::do_unfold(
+            &ctx,
+            &blobstore,
+            changes,
+            parents,
+            Some(path("dir")),
+            root_unode,
+        )
+        .await?;
+        match change.change_type {
+            DeletedManifestChangeType::CreateDeleted => {}
+            _ => panic!(
+                "expected disagreeing parents to resolve against the real merged state (dir is \
+                 fully deleted there), not default to RemoveIfNowEmpty"
+            ),
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn derive_with_move_and_content_info_test(fb: FacebookInit) -> Result<(), Error> {
+        // A rename (PathChange::MovedTo) should be recorded in the moves
+        // map returned by derive_with_move_and_content_info, keyed by the
+        // node it creates for the path that moved away; a plain deletion
+        // alongside it should not show up in that map at all. Neither path
+        // here goes through a real diff (the `PathTree` below is built by
+        // hand), so the returned `contents` map should stay empty.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let cs_id = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("x", "1")
+            .commit()
+            .await?;
+
+        let changes = PathTree::from_iter(vec![
+            (path("deleted.txt"), Some(PathChange::Remove(None))),
+            (
+                path("moved.txt"),
+                Some(PathChange::MovedTo(path("moved-to.txt"))),
+            ),
+        ]);
+
+        let root_unode = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootUnodeManifestId>(&ctx, cs_id, None)
+            .await?
+            .manifest_unode_id()
+            .clone();
+
+        let (mf_id, moves, contents) =
+            DeletedManifestDeriver::<DeletedManifest>::derive_with_move_and_content_info(
+                &ctx, &blobstore, cs_id, vec![], changes, root_unode,
+            )
+            .await?;
+
+        let (_, moved_node_id) =
+            find_entry::<DeletedManifest>(&ctx, &blobstore, mf_id, Some(&path("moved.txt")))
+                .await?
+                .expect("moved.txt should be recorded as deleted");
+        let (_, deleted_node_id) =
+            find_entry::<DeletedManifest>(&ctx, &blobstore, mf_id, Some(&path("deleted.txt")))
+                .await?
+                .expect("deleted.txt should be recorded as deleted");
+
+        assert_eq!(moves.len(), 1);
+        assert_eq!(moves.get(&moved_node_id), Some(&path("moved-to.txt")));
+        assert_eq!(moves.get(&deleted_node_id), None);
+        assert!(contents.is_empty());
+
+        // The `moved_to` accessor is the intended way callers reach this
+        // same information, rather than indexing `moves` directly.
+        assert_eq!(
+            moved_to::<DeletedManifest>(&moves, &moved_node_id),
+            Some(&path("moved-to.txt"))
+        );
+        assert_eq!(moved_to::<DeletedManifest>(&moves, &deleted_node_id), None);
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn derive_captures_deleted_content_test(fb: FacebookInit) -> Result<(), Error> {
+        // A real deletion, derived through `get_changes`'s actual unode
+        // diff (not a hand-built `PathTree`), should have its pre-deletion
+        // content identity captured in the `contents` map and retrievable
+        // via `deleted_content`.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let parent_cs_id = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("to-delete.txt", "content")
+            .commit()
+            .await?;
+        let cs_id = CreateCommitContext::new(&ctx, &repo, vec![parent_cs_id])
+            .delete_file("to-delete.txt")
+            .commit()
+            .await?;
+        let bcs = cs_id.load(&ctx, &blobstore).await?;
+
+        let (changes, root_unode) = get_changes(
+            &ctx,
+            &repo.repo_derived_data().manager().derivation_context(None),
+            bcs,
+        )
+        .await?;
+
+        let (mf_id, _moves, contents) =
+            DeletedManifestDeriver::<DeletedManifest>::derive_with_move_and_content_info(
+                &ctx,
+                &blobstore,
+                cs_id,
+                vec![],
+                changes,
+                root_unode,
+            )
+            .await?;
+
+        let (_, deleted_node_id) = find_entry::<DeletedManifest>(
+            &ctx,
+            &blobstore,
+            mf_id,
+            Some(&path("to-delete.txt")),
+        )
+        .await?
+        .expect("to-delete.txt should be recorded as deleted");
+
+        assert!(
+            deleted_content::<DeletedManifest>(&contents, &deleted_node_id).is_some(),
+            "pre-deletion content identity should have been captured"
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn recover_deleted_content_test(fb: FacebookInit) -> Result<(), Error> {
+        // Unlike `derive_captures_deleted_content_test` above,
+        // `recover_deleted_content` takes only `linknode` -- a field that's
+        // genuinely persisted on the deleted node -- so it works with no
+        // `contents` map in hand at all, e.g. a node derived by a previous,
+        // entirely separate process.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+        let derivation_ctx = repo.repo_derived_data().manager().derivation_context(None);
+
+        let parent_cs_id = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("to-delete.txt", "content")
+            .commit()
+            .await?;
+        let cs_id = CreateCommitContext::new(&ctx, &repo, vec![parent_cs_id.clone()])
+            .delete_file("to-delete.txt")
+            .commit()
+            .await?;
+
+        // Derive the deleted manifest the normal, production way -- no
+        // `derive_with_move_and_content_info` call in sight, so there's no
+        // ephemeral `contents` map this test could even pass around.
+        let root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, cs_id.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        let linknode = match find_deletion_linknode::<DeletedManifest>(
+            &ctx,
+            &blobstore,
+            root_mf_id,
+            Some(&path("to-delete.txt")),
+        )
+        .await?
+        {
+            DeletedPathStatus::DeletedFile(linknode) => linknode,
+            other => panic!("expected to-delete.txt to be a deleted file, got {:?}", other),
+        };
+        assert_eq!(linknode, cs_id);
+
+        let recovered = recover_deleted_content(
+            &ctx,
+            &derivation_ctx,
+            linknode,
+            &path("to-delete.txt"),
+        )
+        .await?
+        .expect("pre-deletion content should be recoverable from linknode alone");
+
+        let expected_file_unode_id = match find_unode_entry_at_path(
+            &ctx,
+            &blobstore,
+            repo.repo_derived_data()
+                .manager()
+                .derive::<RootUnodeManifestId>(&ctx, parent_cs_id, None)
+                .await?
+                .manifest_unode_id(),
+            &path("to-delete.txt"),
+        )
+        .await?
+        .expect("to-delete.txt should still be live in the parent's own unode manifest")
+        {
+            Entry::Leaf(file_unode_id) => file_unode_id,
+            Entry::Tree(_) => panic!("to-delete.txt should be a file, not a directory"),
+        };
+
+        match recovered {
+            Entry::Leaf(file_unode_id) => assert_eq!(file_unode_id, expected_file_unode_id),
+            Entry::Tree(_) => panic!("recovered entry should be a file, not a directory"),
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn deletion_linknode_lookup_test(fb: FacebookInit) -> Result<(), Error> {
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file.txt", "1")
+            .add_file("dir/f-1", "2")
+            .add_file("dir/f-2", "3")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a])
+            .delete_file("file.txt")
+            .delete_file("dir/f-1")
+            .commit()
+            .await?;
+
+        let root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, b.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        // A deleted file, a live file, a live directory with a deleted
+        // descendant, and a path that was never recorded at all.
+        assert_eq!(
+            find_deletion_linknode::<DeletedManifest>(
+                &ctx,
+                &blobstore,
+                root_mf_id.clone(),
+                Some(&path("file.txt")),
+            )
+            .await?,
+            DeletedPathStatus::DeletedFile(b.clone()),
+        );
+        assert_eq!(
+            find_deletion_linknode::<DeletedManifest>(
+                &ctx,
+                &blobstore,
+                root_mf_id.clone(),
+                Some(&path("dir/f-2")),
+            )
+            .await?,
+            DeletedPathStatus::Live,
+        );
+        assert_eq!(
+            find_deletion_linknode::<DeletedManifest>(
+                &ctx,
+                &blobstore,
+                root_mf_id.clone(),
+                Some(&path("dir")),
+            )
+            .await?,
+            DeletedPathStatus::Live,
+        );
+        assert_eq!(
+            find_deletion_linknode::<DeletedManifest>(
+                &ctx,
+                &blobstore,
+                root_mf_id.clone(),
+                Some(&path("never-existed.txt")),
+            )
+            .await?,
+            DeletedPathStatus::Live,
+        );
+
+        let mut deleted_paths =
+            list_deleted_paths::<DeletedManifest>(ctx.clone(), blobstore.clone(), root_mf_id, None)
+                .try_collect::<Vec<_>>()
+                .await?;
+        deleted_paths.sort_by_key(|(path, ..)| path.clone());
+        assert_eq!(
+            deleted_paths,
+            vec![
+                (Some(path("dir/f-1")), b.clone()),
+                (Some(path("file.txt")), b),
+            ],
+        );
+
+        Ok(())
+    }
+
+    fn diff_entry_path(entry: &DeletedManifestDiffEntry) -> Option<MPath> {
+        match entry {
+            DeletedManifestDiffEntry::NowDeleted(path, _) => path.clone(),
+            DeletedManifestDiffEntry::Reincarnated(path) => path.clone(),
+            DeletedManifestDiffEntry::LinknodeChanged(path, _, _) => path.clone(),
+        }
+    }
+
+    #[fbinit::test]
+    async fn diff_deleted_manifests_test(fb: FacebookInit) -> Result<(), Error> {
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file.txt", "1")
+            .add_file("other.txt", "x")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a])
+            .delete_file("file.txt")
+            .commit()
+            .await?;
+        let c = CreateCommitContext::new(&ctx, &repo, vec![b.clone()])
+            .add_file("file.txt", "2")
+            .delete_file("other.txt")
+            .commit()
+            .await?;
+        let d = CreateCommitContext::new(&ctx, &repo, vec![c.clone()])
+            .delete_file("file.txt")
+            .commit()
+            .await?;
+
+        let manager = repo.repo_derived_data().manager();
+        let b_mf_id = manager
+            .derive::<RootDeletedManifestId>(&ctx, b, None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+        let c_mf_id = manager
+            .derive::<RootDeletedManifestId>(&ctx, c.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+        let d_mf_id = manager
+            .derive::<RootDeletedManifestId>(&ctx, d, None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        let matcher: Arc<dyn DeletedManifestDiffMatcher> = Arc::new(|_: &MPath| true);
+
+        // file.txt reincarnated in c; other.txt newly deleted in c.
+        let mut entries = diff_deleted_manifests::<DeletedManifest>(
+            ctx.clone(),
+            blobstore.clone(),
+            b_mf_id.clone(),
+            c_mf_id,
+            matcher.clone(),
+        )
+        .try_collect::<Vec<_>>()
+        .await?;
+        entries.sort_by_key(diff_entry_path);
+        assert_eq!(
+            entries,
+            vec![
+                DeletedManifestDiffEntry::Reincarnated(Some(path("file.txt"))),
+                DeletedManifestDiffEntry::NowDeleted(Some(path("other.txt")), c),
+            ],
+        );
+
+        // file.txt deleted on both sides, but at a different changeset
+        // (reincarnated and re-deleted in between); other.txt newly deleted.
+        let mut entries = diff_deleted_manifests::<DeletedManifest>(
+            ctx.clone(),
+            blobstore.clone(),
+            b_mf_id,
+            d_mf_id,
+            matcher,
+        )
+        .try_collect::<Vec<_>>()
+        .await?;
+        entries.sort_by_key(diff_entry_path);
+        match entries.as_slice() {
+            [DeletedManifestDiffEntry::LinknodeChanged(p2, base_cs, other_cs), DeletedManifestDiffEntry::NowDeleted(p1, _)] =>
+            {
+                assert_eq!(p1, &Some(path("other.txt")));
+                assert_eq!(p2, &Some(path("file.txt")));
+                assert_ne!(base_cs, other_cs);
+            }
+            other => panic!("unexpected diff entries: {:?}", other),
+        }
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn find_entry_test(fb: FacebookInit) -> Result<(), Error> {
+        // Point-lookup behavior of find_entry itself: a deleted file, a
+        // deleted directory (one whose own linknode is set, not merely
+        // containing deleted descendants), and a path that was never
+        // recorded should each return the right status and node id (or
+        // None, for the never-recorded path).
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file.txt", "1")
+            .add_file("dir/f-1", "2")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a])
+            .delete_file("file.txt")
+            .delete_file("dir/f-1")
+            .commit()
+            .await?;
+
+        let root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, b.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        let (file_status, file_mf_id) =
+            find_entry::<DeletedManifest>(&ctx, &blobstore, root_mf_id.clone(), Some(&path("file.txt")))
+                .await?
+                .expect("file.txt should be a recorded node");
+        assert_eq!(file_status, DeletedPathStatus::DeletedFile(b.clone()));
+
+        let (dir_status, dir_mf_id) =
+            find_entry::<DeletedManifest>(&ctx, &blobstore, root_mf_id.clone(), Some(&path("dir")))
+                .await?
+                .expect("dir should be a recorded node");
+        assert_eq!(dir_status, DeletedPathStatus::DeletedDirectory(b));
+        assert_ne!(file_mf_id, dir_mf_id);
+
+        assert_eq!(
+            find_entry::<DeletedManifest>(
+                &ctx,
+                &blobstore,
+                root_mf_id,
+                Some(&path("never-existed.txt")),
+            )
+            .await?,
+            None,
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn restore_deleted_paths_test(fb: FacebookInit) -> Result<(), Error> {
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+        let derivation_ctx = repo.repo_derived_data().manager().derivation_context(None);
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("live.txt", "1")
+            .add_file("deleted.txt", "2")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a])
+            .delete_file("deleted.txt")
+            .commit()
+            .await?;
+
+        let root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, b.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        let paths = vec![
+            path("live.txt"),
+            path("deleted.txt"),
+            path("never-existed.txt"),
+        ];
+
+        let (changesets, mut skipped) = restore_deleted_paths::<DeletedManifest>(
+            &ctx,
+            &derivation_ctx,
+            &repo,
+            root_mf_id,
+            b.clone(),
+            paths,
+            |paths| vec![paths],
+            |_| RestoreChangesetArgs {
+                author: "author".to_string(),
+                message: "restore".to_string(),
+            },
+        )
+        .await?;
+
+        assert_eq!(changesets.len(), 1, "deleted.txt should have been restored");
+        let restore_cs_id = changesets[0];
+        let restore_bcs = restore_cs_id.load(&ctx, &blobstore).await?;
+        assert_eq!(restore_bcs.parents().collect::<Vec<_>>(), vec![b]);
+        let restored_paths: Vec<_> = restore_bcs.file_changes().map(|(p, _)| p.clone()).collect();
+        assert_eq!(restored_paths, vec![path("deleted.txt")]);
+
+        skipped.sort_by_key(|(path, _)| path.clone());
+        assert_eq!(
+            skipped,
+            vec![
+                (path("live.txt"), RestoreSkipReason::PathIsLive),
+                (
+                    path("never-existed.txt"),
+                    RestoreSkipReason::NeverDeleted
+                ),
+            ],
+        );
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn restore_deleted_paths_chains_multiple_chunks_test(fb: FacebookInit) -> Result<(), Error> {
+        // Two deleted files, chunked one-per-commit, should come back as two
+        // chained restore commits (the second parented off the first, not
+        // off the original `parent`), with `changeset_args_factory` called
+        // once per chunk in order.
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+        let derivation_ctx = repo.repo_derived_data().manager().derivation_context(None);
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("one.txt", "1")
+            .add_file("two.txt", "2")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a])
+            .delete_file("one.txt")
+            .delete_file("two.txt")
+            .commit()
+            .await?;
+
+        let root_mf_id = repo
+            .repo_derived_data()
+            .manager()
+            .derive::<RootDeletedManifestId>(&ctx, b.clone(), None)
+            .await?
+            .deleted_manifest_id()
+            .clone();
+
+        let (changesets, skipped) = restore_deleted_paths::<DeletedManifest>(
+            &ctx,
+            &derivation_ctx,
+            &repo,
+            root_mf_id,
+            b.clone(),
+            vec![path("one.txt"), path("two.txt")],
+            |paths| paths.into_iter().map(|p| vec![p]).collect(),
+            |i| RestoreChangesetArgs {
+                author: "author".to_string(),
+                message: format!("restore chunk {}", i),
+            },
+        )
+        .await?;
+
+        assert_eq!(skipped, Vec::new());
+        assert_eq!(changesets.len(), 2);
+
+        let first_bcs = changesets[0].load(&ctx, &blobstore).await?;
+        assert_eq!(first_bcs.parents().collect::<Vec<_>>(), vec![b]);
+        assert_eq!(first_bcs.message(), "restore chunk 0");
+
+        let second_bcs = changesets[1].load(&ctx, &blobstore).await?;
+        assert_eq!(
+            second_bcs.parents().collect::<Vec<_>>(),
+            vec![changesets[0]]
+        );
+        assert_eq!(second_bcs.message(), "restore chunk 1");
+
+        Ok(())
+    }
+
+    #[fbinit::test]
+    async fn deleted_path_history_test(fb: FacebookInit) -> Result<(), Error> {
+        // a: live -> b: deleted -> c: still deleted, untouched (coalesced
+        // away) -> d: reincarnated, live -> e: deleted again (new linknode).
+        let repo: BlobRepo = test_repo_factory::build_empty().unwrap();
+        let ctx = CoreContext::test_mock(fb);
+        let blobstore = repo.blobstore().boxed();
+
+        let a = CreateCommitContext::new_root(&ctx, &repo)
+            .add_file("file.txt", "1")
+            .add_file("other.txt", "x")
+            .commit()
+            .await?;
+        let b = CreateCommitContext::new(&ctx, &repo, vec![a.clone()])
+            .delete_file("file.txt")
+            .commit()
+            .await?;
+        let c = CreateCommitContext::new(&ctx, &repo, vec![b.clone()])
+            .delete_file("other.txt")
+            .commit()
+            .await?;
+        let d = CreateCommitContext::new(&ctx, &repo, vec![c.clone()])
+            .add_file("file.txt", "2")
+            .commit()
+            .await?;
+        let e = CreateCommitContext::new(&ctx, &repo, vec![d.clone()])
+            .delete_file("file.txt")
+            .commit()
+            .await?;
+
+        let manager = repo.repo_derived_data().manager();
+        let mut ancestry = Vec::new();
+        for cs_id in [a.clone(), b.clone(), c.clone(), d.clone(), e.clone()] {
+            let root = manager
+                .derive::<RootDeletedManifestId>(&ctx, cs_id.clone(), None)
+                .await?
+                .deleted_manifest_id()
+                .clone();
+            ancestry.push((cs_id, root));
+        }
+
+        let history =
+            deleted_path_history::<DeletedManifest>(&ctx, &blobstore, &path("file.txt"), ancestry)
+                .await?;
+
+        assert_eq!(
+            history,
+            vec![
+                PathLifecycleEvent::Live(a),
+                PathLifecycleEvent::Deleted(b),
+                PathLifecycleEvent::Live(d),
+                PathLifecycleEvent::Deleted(e),
+            ],
+        );
+
+        Ok(())
+    }
 }
@@ -6,6 +6,7 @@
  */
 
 use std::sync::Arc;
+use std::time::Duration;
 
 use anyhow::{Context, Result};
 use blobstore::Blobstore;
@@ -14,9 +15,10 @@ use changeset_fetcher::ArcChangesetFetcher;
 use context::CoreContext;
 use fbinit::FacebookInit;
 use metaconfig_types::SegmentedChangelogConfig;
+use mononoke_types::{ChangesetId, RepoId};
 use repo_identity::RepoIdentity;
 use sql_construct::{SqlConstruct, SqlConstructFromMetadataDatabaseConfig};
-use sql_ext::replication::NoReplicaLagMonitor;
+use sql_ext::replication::{NoReplicaLagMonitor, ReplicaLagMonitor};
 use sql_ext::SqlConnections;
 
 use crate::iddag::IdDagSaveStore;
@@ -107,3 +109,91 @@ pub async fn new_server_segmented_changelog<'a>(
     };
     Ok(sc)
 }
+
+/// Holds the pieces a seeding entry point needs (idmap factory, changeset
+/// fetcher, bookmarks, blobstore) plus a `run_once` that actually drives
+/// them, so a batch/admin job can seed a repo's segmented changelog from a
+/// cold start.
+pub struct SegmentedChangelogSeeder {
+    repo_id: RepoId,
+    connections: SegmentedChangelogSqlConnections,
+    idmap_factory: IdMapFactory,
+    changeset_fetcher: ArcChangesetFetcher,
+    bookmarks: Arc<dyn Bookmarks>,
+    blobstore: Arc<dyn Blobstore>,
+}
+
+impl SegmentedChangelogSeeder {
+    pub fn new(
+        repo_id: RepoId,
+        connections: SegmentedChangelogSqlConnections,
+        replica_lag_monitor: Arc<dyn ReplicaLagMonitor>,
+        changeset_fetcher: ArcChangesetFetcher,
+        bookmarks: Arc<dyn Bookmarks>,
+        blobstore: Arc<dyn Blobstore>,
+    ) -> Self {
+        let idmap_factory = IdMapFactory::new(connections.0.clone(), replica_lag_monitor, repo_id);
+        Self {
+            repo_id,
+            connections,
+            idmap_factory,
+            changeset_fetcher,
+            bookmarks,
+            blobstore,
+        }
+    }
+
+    /// Builds the idmap + iddag for `heads` and persists them, the same way
+    /// `new_server_segmented_changelog` above does for a long-lived server
+    /// process, but as a one-shot call suitable for a batch/admin job.
+    ///
+    /// This takes `self` by value rather than `&self`: `SegmentedChangelogManager::new`
+    /// needs to own its `idmap_factory`/`changeset_fetcher`/`bookmarks`, and
+    /// `IdMapFactory` isn't known to be `Clone` anywhere this checkout can
+    /// verify, so consuming the seeder avoids guessing at a derive that
+    /// might not be there. A seeder is cheap to reconstruct via `new` if a
+    /// caller needs to seed more than one repo.
+    ///
+    /// `heads` are the changesets the segmented changelog should be seeded
+    /// from, e.g. the repo's current bookmark heads -- the same role
+    /// `seedheads_from_config` plays for `new_server_segmented_changelog`,
+    /// just supplied directly instead of parsed out of a
+    /// `SegmentedChangelogConfig`.
+    pub async fn run_once(self, ctx: &CoreContext, heads: Vec<ChangesetId>) -> Result<()> {
+        let sc_version_store =
+            SegmentedChangelogVersionStore::new(self.connections.0.clone(), self.repo_id);
+        let iddag_save_store = IdDagSaveStore::new(self.repo_id, self.blobstore.clone());
+        let clone_hints =
+            CloneHints::new(self.connections.0, self.repo_id, self.blobstore.clone());
+        let manager = SegmentedChangelogManager::new(
+            self.repo_id,
+            sc_version_store,
+            iddag_save_store,
+            self.idmap_factory,
+            self.changeset_fetcher,
+            self.bookmarks,
+            heads,
+            // A one-shot seeding run has no periodic reload loop to space
+            // out, so there's nothing for this period to govern here;
+            // `SegmentedChangelogManager::load` below doesn't consult it.
+            Duration::from_secs(0),
+            Some(clone_hints),
+        );
+        manager
+            .load(ctx)
+            .await
+            .context("seeding segmented changelog")?;
+        Ok(())
+    }
+}
+
+// No test module: `run_once` only does real work through `SegmentedChangelogManager`,
+// `IdMapFactory`, `SegmentedChangelogVersionStore`, and `IdDagSaveStore`, all
+// declared via `use crate::{iddag, idmap, manager, ...}` at the top of this
+// file -- but this checkout has no `lib.rs` for this crate and none of those
+// sibling modules exist on disk (`find eden/mononoke/segmented_changelog`
+// turns up only this one file). There's no real blobstore, changeset
+// fetcher, or bookmarks implementation reachable from here either, so a test
+// would have to invent every one of `run_once`'s dependencies rather than
+// exercise the real ones. Exercising this for real needs those missing
+// modules restored to the checkout first.
@@ -14,6 +14,11 @@ use std::collections::HashSet;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering::Relaxed;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Weak;
+use std::time::Duration;
+use std::time::Instant;
 
 use auth::AuthGroup;
 use clientinfo::ClientInfo;
@@ -69,6 +74,9 @@ pub fn http_config(
         .map(|auth| (auth.cert, auth.key, auth.cacerts))
         .unwrap_or_default();
 
+    set_compression_preference(compression_config(config));
+    set_stall_timeout(stall_timeout_config(config));
+
     http_client::Config {
         cert_path: cert,
         key_path: key,
@@ -93,12 +101,216 @@ pub fn http_config(
     }
 }
 
+/// Content-encoding requested for HTTP request/response bodies via the
+/// `http.compression` config (`zstd`, `gzip`, or `none`/unset).
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Compression {
+    None,
+    Zstd,
+    Gzip,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::None
+    }
+}
+
+impl Compression {
+    fn as_metric_label(self) -> &'static str {
+        match self {
+            Compression::None => "none",
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    fn from_metric_label(label: usize) -> Self {
+        match label {
+            1 => Compression::Zstd,
+            2 => Compression::Gzip,
+            _ => Compression::None,
+        }
+    }
+
+    fn to_tag(self) -> usize {
+        match self {
+            Compression::None => 0,
+            Compression::Zstd => 1,
+            Compression::Gzip => 2,
+        }
+    }
+}
+
+/// Parses the `http.compression` config. An unrecognized value counts
+/// against `http.compression.invalid_config` (see `bump_counters` for why
+/// this crate reports via counters rather than a logging macro) instead of
+/// silently disabling compression the same way an unset value does -- a typo
+/// like `http.compression=zstdd` should be observable, not indistinguishable
+/// from never having set the config at all.
+pub fn compression_config(config: &dyn configmodel::Config) -> Compression {
+    match config
+        .get_opt::<String>("http", "compression")
+        .unwrap_or(None)
+        .as_deref()
+    {
+        None | Some("") => Compression::None,
+        Some("zstd") => Compression::Zstd,
+        Some("gzip") => Compression::Gzip,
+        Some(_) => {
+            increment_counter("http.compression.invalid_config".to_string(), 1);
+            Compression::None
+        }
+    }
+}
+
+/// The `Accept-Encoding` header value for a given compression preference, or
+/// `None` when no compression is requested.
+///
+/// This is as far as real wire-level compression can go in this crate:
+/// actually attaching this header (and decompressing the response body, and
+/// compressing an upload body) is a property of the HTTP transport, which
+/// lives in `http_client`. That crate's source isn't part of this checkout,
+/// and the only methods this file ever calls on its `Request`/`RequestContext`
+/// types are `ctx_mut()`, `event_listeners()`, `cancellation_handle()`,
+/// `body()`, and `url()` -- none of them a header or body setter. Guessing at a method name
+/// like `set_header` to wire this in would be inventing an unverified API, so
+/// this stops at producing the correct value, ready to pass to `http_client`
+/// once it exposes a way to set it.
+pub fn accept_encoding_header(compression: Compression) -> Option<&'static str> {
+    match compression {
+        Compression::None => None,
+        Compression::Zstd => Some("zstd"),
+        Compression::Gzip => Some("gzip"),
+    }
+}
+
+// Compression preference most recently parsed by `http_config`, stored the
+// same way as `STALL_TIMEOUT_SECS` below: read fresh wherever it's needed
+// rather than threaded as a parameter, since `bump_counters` only gets a
+// `client_id` and `Stats`, not the `configmodel::Config` that produced them.
+static COMPRESSION_PREFERENCE: AtomicUsize = AtomicUsize::new(0);
+
+/// Sets the process-wide compression preference, as parsed by
+/// [`compression_config`]. Called from `http_config` so every caller that
+/// builds an `http_client::Config` also updates this.
+///
+/// `http_client::Config` in this checkout has no field to carry a
+/// compression choice onto, so this can't yet make `http_client` send an
+/// `Accept-Encoding` header, stream-decompress a response, or compress an
+/// upload body -- that's `http_client`'s request/response body handling,
+/// which isn't part of this checkout (nothing in this checkout outside this
+/// crate references `http_client` at all, so there's no sibling
+/// implementation to match either). What this preference does drive for
+/// real: `bump_counters` below tags its metrics with it, so which
+/// compression mode was configured is actually observable in the counters
+/// this crate already emits, rather than being parsed and discarded.
+pub fn set_compression_preference(compression: Compression) {
+    COMPRESSION_PREFERENCE.store(compression.to_tag(), Relaxed);
+}
+
 static INSECURE_MODE: AtomicBool = AtomicBool::new(false);
 
 pub fn enable_insecure_mode() {
     INSECURE_MODE.store(true, Relaxed);
 }
 
+// Idle/stall timeout applied to every future HTTP request, in seconds. 0
+// means disabled. Read fresh for each new request, so changing it takes
+// effect immediately rather than only at `enable_progress_reporting` time.
+static STALL_TIMEOUT_SECS: AtomicUsize = AtomicUsize::new(0);
+
+/// Parses the `http.stall-timeout-seconds` config. Unset or `0` disables the
+/// stall timeout.
+fn stall_timeout_config(config: &dyn configmodel::Config) -> Option<Duration> {
+    let secs = config
+        .get_opt::<u64>("http", "stall-timeout-seconds")
+        .unwrap_or(None)
+        .unwrap_or(0);
+    if secs == 0 {
+        None
+    } else {
+        Some(Duration::from_secs(secs))
+    }
+}
+
+/// Sets the idle/stall timeout applied to every future HTTP request: if a
+/// request goes this long without sending or receiving any bytes, it is
+/// cancelled. Pass `None` (the default) to disable. Driven by the
+/// `http.stall-timeout-seconds` config via `http_config`, which calls this
+/// on every `Config` build so a changed value takes effect on the next
+/// request setup. The timeout is installed by the per-request hook that
+/// `enable_progress_reporting` sets up; without that call it has no effect.
+pub fn set_stall_timeout(timeout: Option<Duration>) {
+    STALL_TIMEOUT_SECS.store(timeout.map_or(0, |t| t.as_secs() as usize), Relaxed);
+}
+
+/// Watches `last_activity` and cancels the request once it's gone silent
+/// for longer than `timeout`. Takes a `Weak` reference: once the request
+/// completes normally and drops its strong references to `last_activity`,
+/// the next wake-up fails to upgrade and the loop exits on its own,
+/// instead of polling forever for a request that's already done.
+async fn watch_for_stall(
+    last_activity: Weak<Mutex<Instant>>,
+    timeout: Duration,
+    cancel: http_client::CancellationHandle,
+) {
+    // Checked well inside `timeout`, not just once at the end of it, so a
+    // stall is noticed promptly and a completed request's watcher exits
+    // promptly too, rather than lingering for up to one more `timeout`.
+    let poll_interval = std::cmp::max(timeout / 4, Duration::from_millis(100));
+    loop {
+        tokio::time::sleep(poll_interval).await;
+        let last_activity = match last_activity.upgrade() {
+            Some(last_activity) => last_activity,
+            None => return,
+        };
+        let idle = last_activity.lock().unwrap().elapsed();
+        if idle >= timeout {
+            cancel.cancel();
+            return;
+        }
+    }
+}
+
+/// Which progress bar (and counting behavior) a request's traffic should be
+/// attributed to.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum RequestBucket {
+    /// Upload-dominant request (non-empty body), e.g. pushing commit data.
+    Upload,
+    /// Download of tree data; consolidated into one bar across requests.
+    Trees,
+    /// Download of file data; consolidated into one bar across requests.
+    Files,
+    /// Anything else; gets its own one-off progress bar.
+    Other,
+}
+
+impl RequestBucket {
+    /// Whether this bucket gets a fresh, one-off progress bar per request
+    /// (as opposed to one shared bar that every request in the bucket
+    /// extends).
+    fn is_single_bar(self) -> bool {
+        matches!(self, RequestBucket::Other)
+    }
+}
+
+/// Classifies a request by URL and upload-ness for progress-bar bucketing.
+/// `is_upload` takes priority: an upload with a `/trees`-looking URL is
+/// still upload-dominant traffic, not a tree download.
+fn classify_request(is_upload: bool, url: &str) -> RequestBucket {
+    if is_upload {
+        RequestBucket::Upload
+    } else if url.ends_with("/trees") {
+        RequestBucket::Trees
+    } else if url.ends_with("/files") || url.ends_with("/files2") {
+        RequestBucket::Files
+    } else {
+        RequestBucket::Other
+    }
+}
+
 /// Setup progress reporting to the main progress registry for the lifetime of
 /// this process.
 pub fn enable_progress_reporting() {
@@ -109,6 +321,7 @@ pub fn enable_progress_reporting() {
 static PROGRESS_REPORTING_STATE: Lazy<Box<dyn Send + Sync>> = Lazy::new(|| {
     let trees_bar = AggregatingProgressBar::new("downloading", "bytes");
     let files_bar = AggregatingProgressBar::new("downloading", "bytes");
+    let uploading_bar = AggregatingProgressBar::new("uploading", "bytes");
 
     Request::on_new_request(move |req| {
         TOTAL.request_count.fetch_add(1, Relaxed);
@@ -124,35 +337,69 @@ static PROGRESS_REPORTING_STATE: Lazy<Box<dyn Send + Sync>> = Lazy::new(|| {
             }
         });
 
-        // TODO: How to tell whether it is downloading or uploading?
+        let stall_timeout_secs = STALL_TIMEOUT_SECS.load(Relaxed);
+        if stall_timeout_secs > 0 {
+            let timeout = Duration::from_secs(stall_timeout_secs as u64);
+            let last_activity = Arc::new(Mutex::new(Instant::now()));
+            let cancel = req.ctx_mut().cancellation_handle();
+            req.ctx_mut().event_listeners().on_download_bytes({
+                let last_activity = last_activity.clone();
+                move |_req, _n| {
+                    *last_activity.lock().unwrap() = Instant::now();
+                }
+            });
+            req.ctx_mut().event_listeners().on_upload_bytes({
+                let last_activity = last_activity.clone();
+                move |_req, _n| {
+                    *last_activity.lock().unwrap() = Instant::now();
+                }
+            });
+            async_runtime::spawn(watch_for_stall(
+                Arc::downgrade(&last_activity),
+                timeout,
+                cancel,
+            ));
+        }
+
+        // A request with a non-empty body is upload-dominant (e.g. pushing
+        // commit data); otherwise it's a plain fetch and download-dominant.
+        let is_upload = req.ctx_mut().body().map_or(false, |body| !body.is_empty());
 
-        // Consolidate /trees and /files requests into single progress bars.
+        // Consolidate /trees and /files requests into single download bars.
         let url = req.ctx_mut().url().to_string();
-        let mut is_single_bar = false;
-        let bar = if url.ends_with("/trees") {
-            trees_bar.create_or_extend(0)
-        } else if url.ends_with("/files") || url.ends_with("/files2") {
-            files_bar.create_or_extend(0)
-        } else {
-            is_single_bar = true;
-            ProgressBar::new("downloading", 0, "bytes")
+        let bucket = classify_request(is_upload, &url);
+        let is_single_bar = bucket.is_single_bar();
+        let bar = match bucket {
+            RequestBucket::Upload => uploading_bar.create_or_extend(0),
+            RequestBucket::Trees => trees_bar.create_or_extend(0),
+            RequestBucket::Files => files_bar.create_or_extend(0),
+            RequestBucket::Other => ProgressBar::new("downloading", 0, "bytes"),
         };
 
         bar.set_message(url);
 
         let req_listeners = req.ctx_mut().event_listeners();
-        req_listeners.on_content_length({
-            let bar = bar.clone();
-            move |_req, n| {
-                bar.increase_total(n as _);
-            }
-        });
-        req_listeners.on_download_bytes({
-            let bar = bar.clone();
-            move |_req, n| {
-                bar.increase_position(n as _);
-            }
-        });
+        if is_upload {
+            req_listeners.on_upload_bytes({
+                let bar = bar.clone();
+                move |_req, n| {
+                    bar.increase_position(n as _);
+                }
+            });
+        } else {
+            req_listeners.on_content_length({
+                let bar = bar.clone();
+                move |_req, n| {
+                    bar.increase_total(n as _);
+                }
+            });
+            req_listeners.on_download_bytes({
+                let bar = bar.clone();
+                move |_req, n| {
+                    bar.increase_position(n as _);
+                }
+            });
+        }
         if is_single_bar {
             req_listeners.on_first_activity(move |_req| {
                 let registry = Registry::main();
@@ -161,30 +408,48 @@ static PROGRESS_REPORTING_STATE: Lazy<Box<dyn Send + Sync>> = Lazy::new(|| {
         }
     });
 
-    // HTTP I/O time series.
-    let take_sample = {
-        || {
-            IoSample::from_io_bytes_count(
-                TOTAL.download_bytes.load(Relaxed) as _,
-                TOTAL.upload_bytes.load(Relaxed) as _,
-                TOTAL.request_count.load(Relaxed) as _,
-            )
-        }
+    // HTTP I/O time series, upload and download tracked as distinct series so
+    // the UI can show both directions instead of one combined line.
+    let take_download_sample = || {
+        IoSample::from_io_bytes_count(
+            TOTAL.download_bytes.load(Relaxed) as _,
+            0,
+            TOTAL.request_count.load(Relaxed) as _,
+        )
+    };
+    let take_upload_sample = || {
+        IoSample::from_io_bytes_count(
+            0,
+            TOTAL.upload_bytes.load(Relaxed) as _,
+            TOTAL.request_count.load(Relaxed) as _,
+        )
     };
 
-    let net_time_series = IoTimeSeries::new("HTTP", "requests");
-    let task = net_time_series.async_sampling(take_sample, IoTimeSeries::default_sample_interval());
-    async_runtime::spawn(task);
+    let download_time_series = IoTimeSeries::new("HTTP download", "requests");
+    let upload_time_series = IoTimeSeries::new("HTTP upload", "requests");
+    async_runtime::spawn(download_time_series.async_sampling(
+        take_download_sample,
+        IoTimeSeries::default_sample_interval(),
+    ));
+    async_runtime::spawn(upload_time_series.async_sampling(
+        take_upload_sample,
+        IoTimeSeries::default_sample_interval(),
+    ));
 
     let registry = Registry::main();
-    registry.register_io_time_series(&net_time_series);
+    registry.register_io_time_series(&download_time_series);
+    registry.register_io_time_series(&upload_time_series);
 
-    Box::new(net_time_series)
+    Box::new((download_time_series, upload_time_series))
 });
 
+// Bucket boundaries (milliseconds) shared by the request-time and
+// response-delay histograms; chosen to give decent p50/p95/p99 resolution
+// across both a fast tree fetch and a slow push.
+const LATENCY_BUCKETS_MS: &[usize] = &[10, 25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000];
+
 fn bump_counters(client_id: &str, stats: &Stats) {
     let n = |suffix: &'static str| -> String { format!("http.{}.{}", client_id, suffix) };
-    // TODO: gauges: rx_bytes and tx_bytes; histograms: request_time_ms, response_delay_ms
     increment_counter(n("total_rx_bytes"), stats.downloaded);
     increment_counter(n("total_tx_bytes"), stats.uploaded);
     increment_counter(n("num_requests"), stats.requests);
@@ -192,7 +457,50 @@ fn bump_counters(client_id: &str, stats: &Stats) {
     increment_counter(
         n("total_response_delay_ms"),
         stats.latency.as_millis() as usize,
-    )
+    );
+
+    // `hg_metrics` only exposes `increment_counter` in this checkout --
+    // there's no histogram or gauge primitive to call, so rather than
+    // invent `record_histogram`/`set_gauge` entry points that may not
+    // exist, latency distributions are reported as cumulative bucketed
+    // counters instead. `n("request_time_ms.le_100")` is "count of
+    // requests that took <= 100ms"; operators can derive p50/p95/p99 from
+    // the bucket counts the same way they would from a real histogram.
+    record_latency_buckets(&n("request_time_ms"), stats.time.as_millis() as usize);
+    record_latency_buckets(&n("response_delay_ms"), stats.latency.as_millis() as usize);
+
+    // Tag the configured compression preference onto the counters this
+    // function already emits, so `http.compression` is observable even
+    // though nothing in this checkout can make `http_client` act on it yet
+    // (see `set_compression_preference`).
+    let compression = Compression::from_metric_label(COMPRESSION_PREFERENCE.load(Relaxed));
+    increment_counter(
+        format!(
+            "http.{}.compression.{}",
+            client_id,
+            compression.as_metric_label()
+        ),
+        1,
+    );
+}
+
+/// Every bucket boundary in `LATENCY_BUCKETS_MS` that `value_ms` falls under
+/// ("le" = less-or-equal), i.e. every cumulative bucket `record_latency_buckets`
+/// increments for this value. Split out as a pure function so the bucketing
+/// math is testable without going through `increment_counter`, which this
+/// checkout has no read-back for.
+fn matching_bucket_bounds(value_ms: usize) -> Vec<usize> {
+    LATENCY_BUCKETS_MS
+        .iter()
+        .copied()
+        .filter(|bound| value_ms <= *bound)
+        .collect()
+}
+
+fn record_latency_buckets(metric: &str, value_ms: usize) {
+    for bound in matching_bucket_bounds(value_ms) {
+        increment_counter(format!("{}.le_{}", metric, bound), 1);
+    }
 }
 
 #[cfg(test)]
@@ -213,4 +521,85 @@ mod tests {
         hg_config.insert("http.convert-cert".into(), "false".into());
         assert!(!http_config(&hg_config, None).convert_cert);
     }
+
+    #[test]
+    fn test_stall_timeout_config() {
+        let mut hg_config = BTreeMap::<String, String>::new();
+        assert_eq!(stall_timeout_config(&hg_config), None);
+
+        hg_config.insert("http.stall-timeout-seconds".into(), "0".into());
+        assert_eq!(stall_timeout_config(&hg_config), None);
+
+        hg_config.insert("http.stall-timeout-seconds".into(), "30".into());
+        assert_eq!(
+            stall_timeout_config(&hg_config),
+            Some(Duration::from_secs(30))
+        );
+    }
+
+    #[test]
+    fn test_compression_config() {
+        let mut hg_config = BTreeMap::<String, String>::new();
+        assert_eq!(compression_config(&hg_config), Compression::None);
+
+        hg_config.insert("http.compression".into(), "zstd".into());
+        assert_eq!(compression_config(&hg_config), Compression::Zstd);
+
+        hg_config.insert("http.compression".into(), "gzip".into());
+        assert_eq!(compression_config(&hg_config), Compression::Gzip);
+
+        hg_config.insert("http.compression".into(), "zstdd".into());
+        assert_eq!(compression_config(&hg_config), Compression::None);
+    }
+
+    #[test]
+    fn test_accept_encoding_header() {
+        assert_eq!(accept_encoding_header(Compression::None), None);
+        assert_eq!(accept_encoding_header(Compression::Zstd), Some("zstd"));
+        assert_eq!(accept_encoding_header(Compression::Gzip), Some("gzip"));
+    }
+
+    #[test]
+    fn test_classify_request() {
+        assert_eq!(
+            classify_request(true, "https://x/trees"),
+            RequestBucket::Upload
+        );
+        assert_eq!(
+            classify_request(false, "https://x/trees"),
+            RequestBucket::Trees
+        );
+        assert_eq!(
+            classify_request(false, "https://x/files"),
+            RequestBucket::Files
+        );
+        assert_eq!(
+            classify_request(false, "https://x/files2"),
+            RequestBucket::Files
+        );
+        assert_eq!(
+            classify_request(false, "https://x/commit"),
+            RequestBucket::Other
+        );
+    }
+
+    #[test]
+    fn test_request_bucket_is_single_bar() {
+        assert!(!RequestBucket::Upload.is_single_bar());
+        assert!(RequestBucket::Other.is_single_bar());
+        assert!(!RequestBucket::Trees.is_single_bar());
+        assert!(!RequestBucket::Files.is_single_bar());
+    }
+
+    #[test]
+    fn test_matching_bucket_bounds() {
+        assert_eq!(matching_bucket_bounds(0), LATENCY_BUCKETS_MS.to_vec());
+        assert_eq!(matching_bucket_bounds(10), LATENCY_BUCKETS_MS.to_vec());
+        assert_eq!(
+            matching_bucket_bounds(11),
+            vec![25, 50, 100, 250, 500, 1000, 2500, 5000, 10000, 30000]
+        );
+        assert_eq!(matching_bucket_bounds(30000), vec![30000]);
+        assert_eq!(matching_bucket_bounds(30001), Vec::<usize>::new());
+    }
 }
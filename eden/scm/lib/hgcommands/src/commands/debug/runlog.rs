@@ -12,56 +12,268 @@ use clidispatch::errors;
 use clidispatch::io::IO;
 use cliparser::define_flags;
 use repo::repo::Repo;
+use serde::Serialize;
 
 define_flags! {
     pub struct DebugRunlogOpts {
-        /// display entries for exited/crashed commands (ADVANCED)
-        ended: bool,
+        /// entries to display: "running" (default), "ended", or "all" (ADVANCED)
+        ended: String,
 
-        /// output template (only allows "json")
+        /// read entries from a previously captured runlog JSON stream instead of the repo
+        /// ("-" reads from stdin); does not require a checkout
+        input: String,
+
+        /// collapse repeated runs of the same command: "full" (default) emits every
+        /// entry, "latest" keeps only the most recent entry per command
+        mode: String,
+
+        /// output directives, comma-separated (e.g. "json:pretty,no-progress"); bare
+        /// "json" is an alias for "json:ndjson"
         #[short('T')]
         template: String,
     }
 }
 
+enum Layout {
+    /// One compact JSON object per line.
+    Ndjson,
+    /// A single minified JSON array of all entries.
+    Compact,
+    /// A single pretty-printed JSON array of all entries.
+    Pretty,
+}
+
+struct JsonOpts {
+    layout: Layout,
+    /// Include the `progress` telemetry field.
+    include_progress: bool,
+    /// Interleave running and ended entries in their natural order, rather
+    /// than grouping all running entries before ended ones.
+    interleave: bool,
+}
+
 enum Format {
     Text,
-    Json,
+    Json(JsonOpts),
+    /// Minimal, stable JSON projection to stdout; all diagnostics go to
+    /// stderr only, so stdout is always a clean stream for a consuming tool
+    /// even when some entries fail to parse.
+    Mixed,
+}
+
+const ACCEPTED_DIRECTIVES: &str =
+    "json, json:ndjson, json:compact, json:pretty, mixed, no-progress, no-interleave";
+
+fn parse_template(template: &str) -> Result<Format> {
+    if template.is_empty() {
+        return Ok(Format::Text);
+    }
+
+    let mut layout = None;
+    let mut include_progress = true;
+    let mut interleave = true;
+    let mut mixed = false;
+
+    for directive in template.split(',') {
+        match directive {
+            "json" => {}
+            "json:ndjson" => layout = Some(Layout::Ndjson),
+            "json:compact" => layout = Some(Layout::Compact),
+            "json:pretty" => layout = Some(Layout::Pretty),
+            "mixed" => mixed = true,
+            "no-progress" => include_progress = false,
+            "no-interleave" => interleave = false,
+            other => {
+                return Err(errors::Abort(format!(
+                    "invalid output directive {:?} (accepted: {})",
+                    other, ACCEPTED_DIRECTIVES
+                ))
+                .into());
+            }
+        }
+    }
+
+    if mixed {
+        return Ok(Format::Mixed);
+    }
+
+    Ok(Format::Json(JsonOpts {
+        layout: layout.unwrap_or(Layout::Ndjson),
+        include_progress,
+        interleave,
+    }))
+}
+
+/// The minimal fields a monitoring script needs, projected from `runlog::Entry`
+/// for the `mixed` format.
+#[derive(Serialize)]
+struct MixedEntry<'a> {
+    id: &'a str,
+    command: &'a [String],
+    pid: u32,
+    start_time: i64,
+    exit_code: Option<i32>,
+    running: bool,
+}
+
+enum Mode {
+    Full,
+    Latest,
+}
+
+/// Which entries (by running/ended status) to display.
+enum StatusFilter {
+    Running,
+    Ended,
+    All,
+}
+
+fn parse_status_filter(ended: &str) -> Result<StatusFilter> {
+    match ended {
+        "" | "running" => Ok(StatusFilter::Running),
+        "ended" => Ok(StatusFilter::Ended),
+        "all" => Ok(StatusFilter::All),
+        other => Err(errors::Abort(format!(
+            "invalid --ended value {:?} (accepted: running, ended, all)",
+            other
+        ))
+        .into()),
+    }
+}
+
+fn parse_mode(mode: &str) -> Result<Mode> {
+    match mode {
+        "" | "full" => Ok(Mode::Full),
+        "latest" => Ok(Mode::Latest),
+        _ => Err(errors::Abort("invalid mode (only \"full\" or \"latest\" supported)".into()).into()),
+    }
+}
+
+/// Collapses `collected` down to the single newest entry per `Entry::id`,
+/// used by `--mode latest` to hide repeated runs of the same invocation.
+fn collapse_latest(collected: Vec<(runlog::Entry, bool)>) -> Vec<(runlog::Entry, bool)> {
+    let mut latest: std::collections::HashMap<String, (runlog::Entry, bool)> =
+        std::collections::HashMap::new();
+    for (entry, running) in collected {
+        latest
+            .entry(entry.id().to_string())
+            .and_modify(|(existing, existing_running)| {
+                if entry.timestamp() >= existing.timestamp() {
+                    *existing = entry.clone();
+                    *existing_running = running;
+                }
+            })
+            .or_insert((entry, running));
+    }
+    latest.into_values().collect()
 }
 
-pub fn run(opts: DebugRunlogOpts, io: &IO, repo: Repo) -> Result<u8> {
+pub fn run(opts: DebugRunlogOpts, io: &IO, repo: Option<Repo>) -> Result<u8> {
     let mut stdout = io.output();
     let mut stderr = io.error();
 
-    let format = match opts.template.as_str() {
-        "json" => Format::Json,
-        "" => Format::Text,
-        _ => return Err(errors::Abort("invalid template (only \"json\" supported)".into()).into()),
-    };
+    let format = parse_template(&opts.template)?;
+    let status_filter = parse_status_filter(&opts.ended)?;
+
+    let mode = parse_mode(&opts.mode)?;
+
+    let entries: Box<dyn Iterator<Item = anyhow::Result<(runlog::Entry, bool)>>> =
+        if !opts.input.is_empty() {
+            Box::new(runlog::entry_iter_from_input(&opts.input)?)
+        } else {
+            let repo = repo.ok_or_else(|| {
+                errors::Abort(
+                    "no checkout found; pass --input to read a captured runlog stream instead"
+                        .to_string(),
+                )
+            })?;
+            Box::new(runlog::FileStore::entry_iter(repo.shared_dot_hg_path())?)
+        };
 
-    for entry in runlog::FileStore::entry_iter(repo.shared_dot_hg_path())? {
-        let (entry, running) = match entry {
-            Ok((entry, running)) => (entry, running),
+    let mut collected = Vec::new();
+    for entry in entries {
+        match entry {
+            Ok(entry) => collected.push(entry),
             Err(err) => {
                 // Unlikely, but it is possible to have incomplete Json files.
                 write!(stderr, "Error reading runlog entry: {:?}\n", err)?;
-                continue;
             }
-        };
-
-        if opts.ended == running {
-            continue;
         }
+    }
+
+    if let Mode::Latest = mode {
+        collected = collapse_latest(collected);
+    }
+
+    match status_filter {
+        StatusFilter::Running => collected.retain(|(_, running)| *running),
+        StatusFilter::Ended => collected.retain(|(_, running)| !*running),
+        // Both statuses survive, so `interleave`/`no-interleave` below has
+        // something to actually group or preserve.
+        StatusFilter::All => {}
+    }
+
+    if let Format::Json(JsonOpts {
+        interleave: false, ..
+    }) = &format
+    {
+        // Stable sort: group running entries before ended ones, otherwise
+        // preserve the order they were discovered in.
+        collected.sort_by_key(|(_, running)| !running);
+    }
 
-        match format {
-            Format::Text => {
+    match format {
+        Format::Text => {
+            for (entry, _running) in &collected {
                 write!(stdout, "{:#?}\n", entry)?;
             }
-            Format::Json => {
-                serde_json::to_writer(&mut stdout, &entry)?;
+        }
+        Format::Mixed => {
+            for (entry, running) in &collected {
+                let projection = MixedEntry {
+                    id: entry.id(),
+                    command: &entry.command,
+                    pid: entry.pid,
+                    start_time: entry.start_time,
+                    exit_code: entry.exit_code,
+                    running: *running,
+                };
+                serde_json::to_writer(&mut stdout, &projection)?;
                 stdout.write_all(&[b'\n'])?;
             }
         }
+        Format::Json(JsonOpts {
+            layout,
+            include_progress,
+            ..
+        }) => {
+            let entries: Vec<runlog::Entry> = collected
+                .into_iter()
+                .map(|(mut entry, _running)| {
+                    if !include_progress {
+                        entry.progress = None;
+                    }
+                    entry
+                })
+                .collect();
+
+            match layout {
+                Layout::Ndjson => {
+                    for entry in &entries {
+                        serde_json::to_writer(&mut stdout, entry)?;
+                        stdout.write_all(&[b'\n'])?;
+                    }
+                }
+                Layout::Compact => {
+                    serde_json::to_writer(&mut stdout, &entries)?;
+                    stdout.write_all(&[b'\n'])?;
+                }
+                Layout::Pretty => {
+                    serde_json::to_writer_pretty(&mut stdout, &entries)?;
+                    stdout.write_all(&[b'\n'])?;
+                }
+            }
+        }
     }
 
     Ok(0)
@@ -74,3 +286,72 @@ pub fn name() -> &'static str {
 pub fn doc() -> &'static str {
     "display runlog entries"
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, timestamp: i64, running: bool) -> runlog::Entry {
+        runlog::Entry {
+            id: id.to_string(),
+            command: vec!["hg".to_string(), "status".to_string()],
+            pid: 1,
+            start_time: timestamp,
+            update_time: None,
+            exit_code: if running { None } else { Some(0) },
+            progress: None,
+        }
+    }
+
+    #[test]
+    fn test_parse_mode() {
+        assert!(matches!(parse_mode(""), Ok(Mode::Full)));
+        assert!(matches!(parse_mode("full"), Ok(Mode::Full)));
+        assert!(matches!(parse_mode("latest"), Ok(Mode::Latest)));
+        assert!(parse_mode("bogus").is_err());
+    }
+
+    #[test]
+    fn test_collapse_latest_keeps_newest_per_id() {
+        let collected = vec![
+            (entry("a", 100, false), false),
+            (entry("a", 200, true), true),
+            (entry("b", 50, false), false),
+        ];
+
+        let mut collapsed = collapse_latest(collected);
+        collapsed.sort_by(|(a, _), (b, _)| a.id().cmp(b.id()));
+
+        assert_eq!(collapsed.len(), 2);
+        assert_eq!(collapsed[0].0.id(), "a");
+        assert_eq!(collapsed[0].0.start_time, 200);
+        assert!(collapsed[0].1);
+        assert_eq!(collapsed[1].0.id(), "b");
+    }
+
+    #[test]
+    fn test_mixed_entry_projects_only_stable_fields() {
+        let e = entry("a", 123, true);
+        let projection = MixedEntry {
+            id: e.id(),
+            command: &e.command,
+            pid: e.pid,
+            start_time: e.start_time,
+            exit_code: e.exit_code,
+            running: true,
+        };
+
+        let value = serde_json::to_value(&projection).unwrap();
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "id": "a",
+                "command": ["hg", "status"],
+                "pid": 1,
+                "start_time": 123,
+                "exit_code": null,
+                "running": true,
+            })
+        );
+    }
+}
@@ -0,0 +1,192 @@
+/*
+ * Copyright (c) Meta Platforms, Inc. and affiliates.
+ *
+ * This software may be used and distributed according to the terms of the
+ * GNU General Public License version 2.
+ */
+
+//! The runlog crate records commands as they run: each invocation writes one
+//! JSON file into the repo's shared `.hg` directory describing the command,
+//! its pid and timing, and whether it has finished. `debugrunlog` and support
+//! bundle tooling read this back to show what is/was running.
+
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+use anyhow::Context as _;
+use anyhow::Result;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// A single recorded command invocation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub id: String,
+    pub command: Vec<String>,
+    pub pid: u32,
+    pub start_time: i64,
+    #[serde(default)]
+    pub update_time: Option<i64>,
+    #[serde(default)]
+    pub exit_code: Option<i32>,
+    /// In-progress progress/telemetry snapshot (e.g. bytes transferred so
+    /// far). Absent once the command has ended.
+    #[serde(default)]
+    pub progress: Option<serde_json::Value>,
+}
+
+impl Entry {
+    /// Whether this entry still represents an in-progress command. An entry
+    /// is running until it has been stamped with an exit code.
+    pub fn is_running(&self) -> bool {
+        self.exit_code.is_none()
+    }
+
+    /// Logical identity used to group repeated runs of the same invocation
+    /// (see `debugrunlog --mode latest`).
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Most recent timestamp recorded for this entry, used to pick the
+    /// newest entry within a group that shares an id.
+    pub fn timestamp(&self) -> i64 {
+        self.update_time.unwrap_or(self.start_time)
+    }
+}
+
+pub struct FileStore;
+
+impl FileStore {
+    /// Iterate every runlog entry persisted under `dir` (one JSON file per
+    /// run), yielding for each one whether the run still appears to be in
+    /// progress.
+    pub fn entry_iter(dir: impl AsRef<Path>) -> Result<impl Iterator<Item = Result<(Entry, bool)>>> {
+        let dir = dir.as_ref().to_path_buf();
+        let read_dir =
+            fs::read_dir(&dir).with_context(|| format!("reading runlog dir {:?}", dir))?;
+
+        Ok(read_dir.filter_map(|dirent| {
+            let dirent = match dirent {
+                Ok(d) => d,
+                Err(err) => return Some(Err(err.into())),
+            };
+            let path = dirent.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                return None;
+            }
+            Some(read_entry(&path))
+        }))
+    }
+}
+
+fn read_entry(path: &Path) -> Result<(Entry, bool)> {
+    let data =
+        fs::read_to_string(path).with_context(|| format!("reading runlog entry {:?}", path))?;
+    parse_entry(&data).with_context(|| format!("parsing runlog entry {:?}", path))
+}
+
+fn parse_entry(data: &str) -> Result<(Entry, bool)> {
+    let entry: Entry = serde_json::from_str(data)?;
+    let running = entry.is_running();
+    Ok((entry, running))
+}
+
+/// Read a stream of previously-emitted entry JSON objects, one per line,
+/// without touching any repository. `spec` is either a filesystem path or
+/// "-" to read from stdin. This makes the format this crate emits
+/// round-trippable: a runlog captured into a support bundle can be fed back
+/// in and rendered exactly as it would have been on the original machine.
+/// Malformed lines are surfaced as `Err` rather than aborting the stream, so
+/// callers can skip them the same way they skip unreadable entry files.
+pub fn entry_iter_from_input(spec: &str) -> Result<impl Iterator<Item = Result<(Entry, bool)>>> {
+    let reader: Box<dyn BufRead> = if spec == "-" {
+        Box::new(BufReader::new(io::stdin()))
+    } else {
+        Box::new(BufReader::new(
+            fs::File::open(spec).with_context(|| format!("opening runlog input {:?}", spec))?,
+        ))
+    };
+
+    Ok(reader.lines().filter_map(|line| {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => return Some(Err(err.into())),
+        };
+        if line.trim().is_empty() {
+            return None;
+        }
+        Some(parse_entry(&line))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write as _;
+
+    use super::*;
+
+    /// Writes `lines` to a uniquely-named file under the system temp dir and
+    /// returns its path; the file is removed when the returned guard drops.
+    struct TempInputFile(std::path::PathBuf);
+
+    impl TempInputFile {
+        fn new(name: &str, lines: &[&str]) -> Self {
+            let path = std::env::temp_dir().join(format!(
+                "runlog-test-{}-{}-{}.ndjson",
+                std::process::id(),
+                name,
+                lines.len()
+            ));
+            let mut file = fs::File::create(&path).unwrap();
+            for line in lines {
+                writeln!(file, "{}", line).unwrap();
+            }
+            TempInputFile(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for TempInputFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_entry_iter_from_input_reads_entries() {
+        let running = r#"{"id":"a","command":["hg","status"],"pid":1,"start_time":100}"#;
+        let ended = r#"{"id":"b","command":["hg","log"],"pid":2,"start_time":200,"exit_code":0}"#;
+        let file = TempInputFile::new("reads-entries", &[running, ended]);
+
+        let results: Vec<(Entry, bool)> = entry_iter_from_input(file.path())
+            .unwrap()
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].0.id(), "a");
+        assert!(results[0].1);
+        assert_eq!(results[1].0.id(), "b");
+        assert!(!results[1].1);
+    }
+
+    #[test]
+    fn test_entry_iter_from_input_skips_blank_lines_and_surfaces_malformed_ones() {
+        let running = r#"{"id":"a","command":["hg","status"],"pid":1,"start_time":100}"#;
+        let file = TempInputFile::new("skips-blank", &["", running, "not json", ""]);
+
+        let results: Vec<Result<(Entry, bool)>> =
+            entry_iter_from_input(file.path()).unwrap().collect();
+
+        assert_eq!(results.len(), 2);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_err());
+    }
+}